@@ -40,6 +40,17 @@ const output = `<span>injecting client scripts...</span>
         }
     });
 
+    document.addEventListener("keydown", function(e){
+        if (e.key === "r" && e.target === document.body && roomScope.Room.selectedObject && roomScope.Room.selectedObject.type === "creep") {
+            let object_id = roomScope.Room.selectedObject._id;
+            Api.post('user/console',{
+                expression: "retreat_creep('"+object_id+"');'retreat sent';",
+                shard: roomScope.Room.shardName,
+                hidden: true
+            });
+        }
+    });
+
     window.client_scripts_injected = true;
 })()
 </script>`.replace(/(\r\n|\n|\r)\t+|(\r\n|\n|\r) +|(\r\n|\n|\r)/gm, '')
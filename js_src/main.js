@@ -8,6 +8,14 @@ import * as screeps_bot from '../pkg/rtsbot.js';
 // glue functions for client scripts
 global.update_selected_object = screeps_bot.update_selected_object;
 global.right_click_position = screeps_bot.right_click_position;
+global.retreat_creep = screeps_bot.retreat_creep;
+global.send_creep_to_shard = screeps_bot.send_creep_to_shard;
+global.export_blueprint = screeps_bot.export_blueprint;
+global.import_blueprint = screeps_bot.import_blueprint;
+global.commit_blueprint = screeps_bot.commit_blueprint;
+global.discard_blueprint = screeps_bot.discard_blueprint;
+global.add_remote = screeps_bot.add_remote;
+global.census = screeps_bot.census;
 
 // This provides the function `console.error` that wasm_bindgen sometimes expects to exist,
 // especially with type checks in debug mode. An alternative is to have this be `function () {}`
@@ -0,0 +1,152 @@
+use screeps::constants::{Part, MAX_CREEP_SIZE};
+
+use crate::movement::MovementProfile;
+
+// the makeup of a role's body: parts it always has, and a set of parts repeated to scale it up
+// to the energy available, with move parts added to keep up the movement profile
+#[derive(Debug, Clone, Copy)]
+pub struct BodySpec {
+    pub base: &'static [Part],
+    pub unit: &'static [Part],
+    pub min_units: u32,
+    pub max_units: u32,
+}
+
+impl BodySpec {
+    // the body with the given number of units, moves included, unsorted
+    fn parts(&self, units: u32, profile: MovementProfile) -> Vec<Part> {
+        let mut parts = self.base.to_vec();
+        for _ in 0..units {
+            parts.extend_from_slice(self.unit);
+        }
+        let moves = profile.move_parts(parts.len() as u32);
+        parts.extend(std::iter::repeat_n(Part::Move, moves as usize));
+        parts
+    }
+}
+
+pub fn body_cost(body: &[Part]) -> u32 {
+    body.iter().map(|part| part.cost()).sum()
+}
+
+// the largest body the spec allows that fits in the energy and the creep size limit, sorted
+// so that tough parts soak up damage first and heal parts go last; if even the smallest
+// doesn't fit, that's returned anyway for the spawn to wait on
+pub fn build_body(spec: &BodySpec, energy: u32, profile: MovementProfile) -> Vec<Part> {
+    let mut body = (spec.min_units..=spec.max_units)
+        .rev()
+        .map(|units| spec.parts(units, profile))
+        .find(|body| body.len() as u32 <= MAX_CREEP_SIZE && body_cost(body) <= energy)
+        .unwrap_or_else(|| spec.parts(spec.min_units, profile));
+    body.sort_by_key(|part| part_order(*part));
+    body
+}
+
+fn part_order(part: Part) -> u8 {
+    match part {
+        Part::Tough => 0,
+        Part::Work => 1,
+        Part::Carry => 2,
+        Part::Attack => 3,
+        Part::RangedAttack => 4,
+        Part::Claim => 5,
+        Part::Move => 6,
+        Part::Heal => 7,
+        _ => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Part::*;
+
+    const WORKER: BodySpec = BodySpec {
+        base: &[],
+        unit: &[Work, Carry],
+        min_units: 1,
+        max_units: 10,
+    };
+
+    const HARVESTER: BodySpec = BodySpec {
+        base: &[Carry],
+        unit: &[Work],
+        min_units: 1,
+        max_units: 5,
+    };
+
+    fn count(body: &[Part], part: Part) -> usize {
+        body.iter().filter(|p| **p == part).count()
+    }
+
+    #[test]
+    fn move_parts_follow_profile() {
+        assert_eq!(MovementProfile::RoadsOneToTwo.move_parts(3), 2);
+        assert_eq!(MovementProfile::RoadsOneToTwo.move_parts(4), 2);
+        assert_eq!(MovementProfile::PlainsOneToOne.move_parts(3), 3);
+        assert_eq!(MovementProfile::SwampFiveToOne.move_parts(2), 10);
+    }
+
+    #[test]
+    fn scales_to_energy() {
+        // each unit on roads is work, carry and move, for 200 energy
+        let body = build_body(&WORKER, 300, MovementProfile::RoadsOneToTwo);
+        assert_eq!(body, vec![Work, Carry, Move]);
+        let body = build_body(&WORKER, 800, MovementProfile::RoadsOneToTwo);
+        assert_eq!(
+            body,
+            vec![Work, Work, Work, Work, Carry, Carry, Carry, Carry, Move, Move, Move, Move]
+        );
+        assert!(body_cost(&body) <= 800);
+
+        // plains needs a move for every part
+        let body = build_body(&WORKER, 800, MovementProfile::PlainsOneToOne);
+        assert_eq!(count(&body, Work), 3);
+        assert_eq!(count(&body, Move), 6);
+        assert!(body_cost(&body) <= 800);
+    }
+
+    #[test]
+    fn base_parts_are_kept() {
+        // carry, one work and one move at the least
+        let body = build_body(&HARVESTER, 300, MovementProfile::RoadsOneToTwo);
+        assert_eq!(body, vec![Work, Carry, Move]);
+        // five works and the carry need three moves
+        let body = build_body(&HARVESTER, 800, MovementProfile::RoadsOneToTwo);
+        assert_eq!(count(&body, Work), 5);
+        assert_eq!(count(&body, Carry), 1);
+        assert_eq!(count(&body, Move), 3);
+    }
+
+    #[test]
+    fn limited_by_max_units_and_size() {
+        let body = build_body(&WORKER, 100_000, MovementProfile::RoadsOneToTwo);
+        assert_eq!(count(&body, Work), 10);
+
+        let spec = BodySpec {
+            max_units: MAX_CREEP_SIZE,
+            ..WORKER
+        };
+        let body = build_body(&spec, 100_000, MovementProfile::PlainsOneToOne);
+        assert_eq!(body.len(), 48);
+        assert_eq!(count(&body, Work), 12);
+    }
+
+    #[test]
+    fn smallest_body_when_unaffordable() {
+        let body = build_body(&HARVESTER, 50, MovementProfile::RoadsOneToTwo);
+        assert_eq!(body, vec![Work, Carry, Move]);
+    }
+
+    #[test]
+    fn sorted_with_tough_first_and_heal_last() {
+        let spec = BodySpec {
+            base: &[Heal, Attack, Tough],
+            unit: &[],
+            min_units: 0,
+            max_units: 0,
+        };
+        let body = build_body(&spec, 10_000, MovementProfile::RoadsOneToTwo);
+        assert_eq!(body, vec![Tough, Attack, Move, Move, Heal]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use screeps::{
+    constants::{CREEP_LIFE_TIME, CREEP_SPAWN_TIME},
+    game,
+    objects::Creep,
+};
+
+use crate::{role::WorkerRole, worker::WorkerId};
+
+// what the census knows about each creep filling a role
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct CensusEntry {
+    pub worker_id: WorkerId,
+    // the tick the creep will die of old age
+    pub expires: u32,
+    pub body_size: u32,
+    pub spawning: bool,
+}
+
+impl CensusEntry {
+    // creeps still spawning get their full lifetime from when their body's done
+    pub fn for_creep(worker_id: WorkerId, creep: &Creep) -> CensusEntry {
+        let body_size = creep.body().len() as u32;
+        let expires = match creep.ticks_to_live() {
+            Some(ticks_to_live) => game::time() + ticks_to_live,
+            None => game::time() + body_size * CREEP_SPAWN_TIME + CREEP_LIFE_TIME,
+        };
+        CensusEntry {
+            worker_id,
+            expires,
+            body_size,
+            spawning: creep.spawning(),
+        }
+    }
+}
+
+// the creeps filling each role, so spawns can tell which roles need filling or replacing
+#[derive(Default, Debug)]
+pub struct RoleCensus {
+    roles: HashMap<WorkerRole, Vec<CensusEntry>>,
+}
+
+impl RoleCensus {
+    // add a worker under its role, or refresh its entry if it's already there
+    pub fn record(&mut self, role: WorkerRole, entry: CensusEntry) {
+        let entries = self.roles.entry(role).or_default();
+        match entries
+            .iter_mut()
+            .find(|existing| existing.worker_id == entry.worker_id)
+        {
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+    }
+
+    // take a worker out of its role, dropping the role once nobody's left filling it
+    pub fn remove(&mut self, role: &WorkerRole, worker_id: WorkerId) {
+        if let Some(entries) = self.roles.get_mut(role) {
+            entries.retain(|entry| entry.worker_id != worker_id);
+            if entries.is_empty() {
+                self.roles.remove(role);
+            }
+        }
+    }
+
+    pub fn roles(&self) -> impl Iterator<Item = &WorkerRole> {
+        self.roles.keys()
+    }
+
+    pub fn entries(&self, role: &WorkerRole) -> &[CensusEntry] {
+        self.roles
+            .get(role)
+            .map_or(&[], |entries| entries.as_slice())
+    }
+
+    pub fn count(&self, role: &WorkerRole) -> usize {
+        self.entries(role).len()
+    }
+
+    // when the longest-lived creep in the role expires, if there are any
+    pub fn latest_expiry(&self, role: &WorkerRole) -> Option<u32> {
+        self.entries(role).iter().map(|entry| entry.expires).max()
+    }
+
+    // a line per role for the console, with each creep's ticks left and body size
+    pub fn report(&self, tick: u32) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .roles
+            .iter()
+            .map(|(role, entries)| {
+                let creeps: Vec<String> = entries
+                    .iter()
+                    .map(|entry| {
+                        let state = if entry.spawning { " spawning" } else { "" };
+                        format!(
+                            "{} ttl, {} parts{}",
+                            entry.expires.saturating_sub(tick),
+                            entry.body_size,
+                            state
+                        )
+                    })
+                    .collect();
+                format!("{:?}: {}", role, creeps.join("; "))
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::role::Hauler;
+    use screeps::local::{ObjectId, RoomName};
+
+    fn hauler(id: u8) -> WorkerRole {
+        WorkerRole::Hauler(Hauler {
+            home_room: RoomName::new("W1N1").unwrap(),
+            id,
+        })
+    }
+
+    fn entry(id: u128, expires: u32) -> CensusEntry {
+        CensusEntry {
+            worker_id: WorkerId::Creep(ObjectId::from_packed(id)),
+            expires,
+            body_size: 3,
+            spawning: false,
+        }
+    }
+
+    #[test]
+    fn records_and_refreshes_entries() {
+        let mut census = RoleCensus::default();
+        census.record(hauler(0), entry(1, 100));
+        census.record(hauler(0), entry(2, 1_500));
+        census.record(hauler(1), entry(3, 200));
+        assert_eq!(census.count(&hauler(0)), 2);
+        assert_eq!(census.count(&hauler(1)), 1);
+        assert_eq!(census.latest_expiry(&hauler(0)), Some(1_500));
+
+        // recording the same worker again updates it rather than adding another
+        census.record(hauler(1), entry(3, 150));
+        assert_eq!(census.count(&hauler(1)), 1);
+        assert_eq!(census.latest_expiry(&hauler(1)), Some(150));
+    }
+
+    #[test]
+    fn removing_a_worker_keeps_the_rest_of_its_role() {
+        let mut census = RoleCensus::default();
+        census.record(hauler(0), entry(1, 100));
+        census.record(hauler(0), entry(2, 1_500));
+
+        // the old creep dying leaves its replacement filling the role
+        census.remove(&hauler(0), WorkerId::Creep(ObjectId::from_packed(1)));
+        assert_eq!(census.count(&hauler(0)), 1);
+        assert_eq!(census.latest_expiry(&hauler(0)), Some(1_500));
+
+        // removing someone who isn't there changes nothing
+        census.remove(&hauler(0), WorkerId::Creep(ObjectId::from_packed(9)));
+        census.remove(&hauler(1), WorkerId::Creep(ObjectId::from_packed(2)));
+        assert_eq!(census.count(&hauler(0)), 1);
+
+        census.remove(&hauler(0), WorkerId::Creep(ObjectId::from_packed(2)));
+        assert_eq!(census.count(&hauler(0)), 0);
+        assert_eq!(census.latest_expiry(&hauler(0)), None);
+        assert_eq!(census.roles().count(), 0);
+    }
+
+    #[test]
+    fn reports_each_role() {
+        let mut census = RoleCensus::default();
+        census.record(hauler(0), entry(1, 600));
+        let mut spawning = entry(2, 1_600);
+        spawning.spawning = true;
+        census.record(hauler(0), spawning);
+
+        let report = census.report(100);
+        assert_eq!(report.len(), 1);
+        assert!(report[0].ends_with("500 ttl, 3 parts; 1500 ttl, 3 parts spawning"));
+    }
+}
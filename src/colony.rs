@@ -0,0 +1,446 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use screeps::{
+    constants::{find, StructureType, Terrain, ROOM_SIZE},
+    game::{self, map::FindRouteOptions},
+    local::{LocalRoomTerrain, ObjectId, Position, RoomCoordinate, RoomName, RoomXY},
+    objects::{Room, RoomTerrain, StructureSpawn},
+    pathfinder::{self, SearchOptions},
+    prelude::*,
+    raw_memory,
+};
+
+use crate::{
+    constants::*,
+    logistics::LogisticsBroker,
+    movement::Heatmap,
+    planner::{
+        exit_tiles, parse_buildings, plan_exit_road, plan_room, to_buildings, validate_blueprint,
+        Blueprint, PlannerInput,
+    },
+    role::WorkerRole,
+    task::Task,
+    ShardState,
+};
+
+// state for each of our owned rooms
+pub struct ColonyState {
+    // off-road tiles out of the way of sources, the controller and spawns, where idle
+    // creeps wait so they don't block anything
+    pub parking: Vec<Position>,
+    // where everything in the room should go at each controller level; planned once, when
+    // we first take the room, so it stays put as the room is built up
+    pub blueprint: Option<Blueprint>,
+    // an imported blueprint being previewed, which replaces the current one once committed
+    pub pending_blueprint: Option<Blueprint>,
+    // controller level when construction sites were last placed from the blueprint, and when
+    // to check again even if it hasn't changed
+    pub construction_rcl: u8,
+    pub next_construction_check: u32,
+    // rooms we mine from, which get a road out to their exit
+    pub remotes: Vec<RoomName>,
+    // built roads that creeps have stopped walking on, which are left to decay rather than
+    // being repaired or rebuilt, until traffic picks back up
+    pub unused_roads: HashSet<RoomXY>,
+    // what's on offer and wanted around the colony this tick
+    pub logistics: LogisticsBroker,
+    // path lengths from spawns to where creeps work, for timing replacements
+    pub travel_times: HashMap<(Position, Position), u32>,
+    // creeps waiting for a spawn to take them on, most important first, and the tick the
+    // queue was built
+    pub spawn_queue: Vec<Task>,
+    pub spawn_queue_tick: u32,
+    // what each spawn has taken on from the queue and is still working on
+    pub spawn_claims: HashMap<ObjectId<StructureSpawn>, WorkerRole>,
+    // set when nothing's left to refill the spawns and they can't afford a proper creep,
+    // until haulers and harvesters are back
+    pub recovering: bool,
+}
+
+impl ColonyState {
+    // set up a newly seen colony, taking the blueprint it had before a global reset if one was
+    // saved, or planning one otherwise
+    pub fn new(room: &Room, heatmap: &Heatmap, saved: Option<&SavedColony>) -> ColonyState {
+        let saved_blueprint = saved
+            .and_then(|saved| saved.blueprint.as_ref())
+            .and_then(|json| match parse_buildings(json) {
+                Ok(blueprint) => Some(blueprint),
+                Err(e) => {
+                    warn!("bad saved blueprint for {}: {}", room.name(), e);
+                    None
+                }
+            });
+        let replanned = saved_blueprint.is_none();
+        let blueprint = saved_blueprint.or_else(|| {
+            PlannerInput::from_room(room, heatmap.room(room.name()))
+                .and_then(|input| plan_room(&input))
+        });
+
+        let mut colony_state = ColonyState {
+            parking: find_parking_tiles(room, heatmap, blueprint.as_ref()),
+            blueprint,
+            pending_blueprint: None,
+            construction_rcl: 0,
+            next_construction_check: 0,
+            remotes: vec![],
+            unused_roads: HashSet::new(),
+            logistics: LogisticsBroker::default(),
+            travel_times: HashMap::new(),
+            spawn_queue: vec![],
+            spawn_queue_tick: 0,
+            spawn_claims: HashMap::new(),
+            recovering: false,
+        };
+
+        // a saved blueprint already has the roads out to the remotes, but a fresh one needs
+        // them planned again
+        for remote in saved.map_or(&[][..], |saved| saved.remotes.as_slice()) {
+            if replanned {
+                if let Err(e) = colony_state.plan_remote_road(room, *remote, heatmap) {
+                    warn!("couldn't plan road to remote {}: {}", remote, e);
+                }
+            }
+            colony_state.remotes.push(*remote);
+        }
+
+        colony_state
+    }
+
+    // recompute anything derived from the room's layout, which may have changed since
+    pub fn update_layout(&mut self, room: &Room, heatmap: &Heatmap) {
+        self.parking = find_parking_tiles(room, heatmap, self.blueprint.as_ref());
+        self.update_unused_roads(room, heatmap);
+        self.travel_times.clear();
+    }
+
+    // note which roads nobody's been walking on, once the heatmap has been recording long
+    // enough to tell; roads drop back out as soon as creeps start using them again
+    fn update_unused_roads(&mut self, room: &Room, heatmap: &Heatmap) {
+        if !heatmap.is_established(game::time()) {
+            return;
+        }
+        let room_heatmap = heatmap.room(room.name());
+        let heat = |xy: RoomXY| room_heatmap.map_or(0, |room_heatmap| room_heatmap.get(xy));
+
+        self.unused_roads
+            .retain(|xy| heat(*xy) < ROAD_MAINTENANCE_MIN_HEAT);
+        for structure in room.find(find::STRUCTURES, None) {
+            let xy = structure.pos().xy();
+            if structure.structure_type() == StructureType::Road
+                && heat(xy) < ROAD_MAINTENANCE_MIN_HEAT
+            {
+                self.unused_roads.insert(xy);
+            }
+        }
+    }
+
+    // start mining from a neighbouring room, planning a road out to the exit towards it;
+    // returns how many tiles of road were added
+    pub fn add_remote(
+        &mut self,
+        room: &Room,
+        remote: RoomName,
+        heatmap: &Heatmap,
+    ) -> Result<usize, String> {
+        if self.remotes.contains(&remote) {
+            return Err(format!("{} is already a remote", remote));
+        }
+        let added = self.plan_remote_road(room, remote, heatmap)?;
+        self.remotes.push(remote);
+        // get the new road's sites down right away
+        self.next_construction_check = 0;
+        Ok(added)
+    }
+
+    // add a road out to the exit towards a remote to the blueprint
+    fn plan_remote_road(
+        &mut self,
+        room: &Room,
+        remote: RoomName,
+        heatmap: &Heatmap,
+    ) -> Result<usize, String> {
+        let direction =
+            game::map::find_exit(room.name(), remote, Some(FindRouteOptions::default()))
+                .map_err(|e| format!("no route to {}: {:?}", remote, e))?;
+        let input = PlannerInput::from_room(room, heatmap.room(room.name()))
+            .ok_or(format!("can't plan roads in {}", room.name()))?;
+
+        Ok(match self.blueprint.as_mut() {
+            Some(blueprint) => plan_exit_road(
+                &input,
+                blueprint,
+                &exit_tiles(&input.terrain, direction),
+                REMOTE_ROAD_RCL,
+            ),
+            None => 0,
+        })
+    }
+
+    // read a blueprint in the buildings format, checking it can be built in the room, and
+    // hold it for preview until it's committed; returns how many structures it has
+    pub fn import_blueprint(&mut self, room_name: RoomName, json: &str) -> Result<usize, String> {
+        let blueprint = parse_buildings(json)?;
+        let terrain = RoomTerrain::new(room_name)
+            .map(LocalRoomTerrain::from)
+            .ok_or(format!("no terrain for {}", room_name))?;
+        let problems = validate_blueprint(&blueprint, &terrain);
+        if !problems.is_empty() {
+            return Err(problems.join("; "));
+        }
+
+        let structure_count = blueprint.structures.len();
+        self.pending_blueprint = Some(blueprint);
+        Ok(structure_count)
+    }
+
+    // switch over to the pending blueprint, placing sites for it right away
+    pub fn commit_blueprint(&mut self) -> bool {
+        match self.pending_blueprint.take() {
+            Some(blueprint) => {
+                self.blueprint = Some(blueprint);
+                self.next_construction_check = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // how many steps it takes to get from one position to within range of the other, found
+    // once with the pathfinder and remembered until the layout's next refreshed
+    pub fn travel_time(&mut self, from: Position, to: Position) -> u32 {
+        *self.travel_times.entry((from, to)).or_insert_with(|| {
+            pathfinder::search(from, to, 1, Some(SearchOptions::default()))
+                .path()
+                .len() as u32
+        })
+    }
+
+    // the closest parking tile to the given position that no other creep has claimed
+    pub fn closest_free_parking(
+        &self,
+        from_position: Position,
+        claimed: &HashSet<Position>,
+    ) -> Option<Position> {
+        self.parking
+            .iter()
+            .filter(|pos| !claimed.contains(pos))
+            .min_by_key(|pos| from_position.get_range_to(**pos))
+            .copied()
+    }
+}
+
+// what's kept of a colony across global resets: the blueprint committed for it, in the
+// buildings format, and the rooms it mines from
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct SavedColony {
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub blueprint: Option<String>,
+    #[serde(rename = "r", default, skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<RoomName>,
+}
+
+// colonies' saved state, mirrored to a memory segment; colonies aren't set up until it's been
+// read, so that they don't plan over a blueprint they were given before the reset
+#[derive(Default)]
+pub struct SavedColonies {
+    colonies: Option<HashMap<RoomName, SavedColony>>,
+    changed: bool,
+}
+
+impl SavedColonies {
+    pub fn is_loaded(&self) -> bool {
+        self.colonies.is_some()
+    }
+
+    pub fn get(&self, room_name: RoomName) -> Option<&SavedColony> {
+        self.colonies
+            .as_ref()
+            .and_then(|colonies| colonies.get(&room_name))
+    }
+
+    fn entry(&mut self, room_name: RoomName) -> &mut SavedColony {
+        self.changed = true;
+        self.colonies
+            .get_or_insert_with(HashMap::new)
+            .entry(room_name)
+            .or_default()
+    }
+
+    pub fn save_blueprint(&mut self, room_name: RoomName, blueprint: &Blueprint) {
+        self.entry(room_name).blueprint = Some(to_buildings(blueprint));
+    }
+
+    // remotes go along with the blueprint, since that's where their roads are
+    pub fn save_remotes(&mut self, room_name: RoomName, colony_state: &ColonyState) {
+        if let Some(blueprint) = colony_state.blueprint.as_ref() {
+            self.save_blueprint(room_name, blueprint);
+        }
+        self.entry(room_name).remotes = colony_state.remotes.clone();
+    }
+
+    // read the saved colonies once the segment's available, then write them back whenever
+    // they change; returns whether they were read this tick. Without a segment there's
+    // nothing to wait for
+    pub fn sync_segment(&mut self, segment: Option<u8>, requested_segments: &mut Vec<u8>) -> bool {
+        let segment = match segment {
+            Some(segment) => segment,
+            None => {
+                let loaded = !self.is_loaded();
+                self.colonies.get_or_insert_with(HashMap::new);
+                return loaded;
+            }
+        };
+
+        if !self.is_loaded() {
+            return match raw_memory::segments().get(segment) {
+                Some(data) => {
+                    self.colonies = Some(Self::load(&data));
+                    true
+                }
+                None => {
+                    requested_segments.push(segment);
+                    false
+                }
+            };
+        }
+
+        if self.changed {
+            self.changed = false;
+            match serde_json::to_string(&self.colonies) {
+                Ok(serialized) => raw_memory::segments().set(segment, serialized),
+                Err(e) => warn!("couldn't serialize saved colonies: {:?}", e),
+            }
+        }
+        false
+    }
+
+    fn load(data: &str) -> HashMap<RoomName, SavedColony> {
+        if data.is_empty() {
+            return HashMap::new();
+        }
+        match serde_json::from_str(data) {
+            Ok(colonies) => colonies,
+            Err(e) => {
+                warn!("couldn't parse saved colonies: {:?}", e);
+                HashMap::new()
+            }
+        }
+    }
+}
+
+// blueprints left in the segment, keyed by room name, get imported for preview and then
+// cleared from it so they're only read once
+pub fn import_blueprints_from_segment(shard_state: &mut ShardState, segment: u8) {
+    let data = match raw_memory::segments().get(segment) {
+        Some(data) => data,
+        None => {
+            shard_state.requested_segments.push(segment);
+            return;
+        }
+    };
+    // keep it active, to notice new blueprints being put there
+    shard_state.requested_segments.push(segment);
+    if data.is_empty() {
+        return;
+    }
+
+    match serde_json::from_str::<HashMap<RoomName, serde_json::Value>>(&data) {
+        Ok(blueprints) => {
+            for (room_name, blueprint) in blueprints {
+                match shard_state.colony_state.get_mut(&room_name) {
+                    Some(colony_state) => {
+                        match colony_state.import_blueprint(room_name, &blueprint.to_string()) {
+                            Ok(count) => info!(
+                                "imported blueprint with {} structures for {}, previewing until committed",
+                                count, room_name
+                            ),
+                            Err(e) => warn!("bad blueprint for {}: {}", room_name, e),
+                        }
+                    }
+                    None => warn!("blueprint for {}, which isn't a colony", room_name),
+                }
+            }
+        }
+        Err(e) => warn!("couldn't parse blueprint segment: {:?}", e),
+    }
+    raw_memory::segments().set(segment, String::new());
+}
+
+// pick a cluster of tiles near the spawn (or controller, before there is one) that nothing
+// else needs: not on a structure or construction site, not on tiles creeps are often walking
+// over, and clear of the room edges and everything creeps need to get to; once there's a
+// blueprint, tiles it has plans for are kept clear too, so nobody's parked in the way of a
+// structure or road being built later
+fn find_parking_tiles(
+    room: &Room,
+    heatmap: &Heatmap,
+    blueprint: Option<&Blueprint>,
+) -> Vec<Position> {
+    let room_name = room.name();
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+
+    let mut blocked = HashSet::new();
+    for structure in room.find(find::STRUCTURES, None) {
+        blocked.insert(structure.pos().xy());
+    }
+    for csite in room.find(find::CONSTRUCTION_SITES, None) {
+        blocked.insert(csite.pos().xy());
+    }
+    if let Some(blueprint) = blueprint {
+        blocked.extend(blueprint.structures.iter().map(|structure| structure.xy));
+    }
+
+    let mut keep_clear = vec![];
+    for source in room.find(find::SOURCES, None) {
+        keep_clear.push((source.pos(), PARKING_SOURCE_CLEARANCE));
+    }
+    for mineral in room.find(find::MINERALS, None) {
+        keep_clear.push((mineral.pos(), PARKING_SOURCE_CLEARANCE));
+    }
+    let spawns = room.find(find::MY_SPAWNS, None);
+    for spawn in spawns.iter() {
+        keep_clear.push((spawn.pos(), PARKING_SPAWN_CLEARANCE));
+    }
+    let controller = room.controller();
+    if let Some(controller) = controller.as_ref() {
+        keep_clear.push((controller.pos(), PARKING_CONTROLLER_CLEARANCE));
+    }
+
+    let anchor = match (spawns.first(), controller) {
+        (Some(spawn), _) => spawn.pos(),
+        (None, Some(controller)) => controller.pos(),
+        (None, None) => return vec![],
+    };
+
+    let mut candidates = vec![];
+    for x in PARKING_EDGE_CLEARANCE..(ROOM_SIZE - PARKING_EDGE_CLEARANCE) {
+        for y in PARKING_EDGE_CLEARANCE..(ROOM_SIZE - PARKING_EDGE_CLEARANCE) {
+            let pos = Position::new(
+                RoomCoordinate::try_from(x).unwrap(),
+                RoomCoordinate::try_from(y).unwrap(),
+                room_name,
+            );
+            if terrain.get(pos.xy()) == Terrain::Wall || blocked.contains(&pos.xy()) {
+                continue;
+            }
+            if heatmap.get(pos) >= PARKING_MAX_HEAT {
+                continue;
+            }
+            if keep_clear
+                .iter()
+                .any(|(clear_pos, range)| pos.get_range_to(*clear_pos) <= *range)
+            {
+                continue;
+            }
+            candidates.push(pos);
+        }
+    }
+
+    // closest to the anchor first, so creeps don't wander off to the far side of the room;
+    // position breaks ties so the result doesn't depend on the order we found them in
+    candidates.sort_by_key(|pos| (anchor.get_range_to(*pos), pos.x().u8(), pos.y().u8()));
+    candidates.truncate(PARKING_TILE_COUNT);
+    candidates
+}
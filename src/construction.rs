@@ -0,0 +1,201 @@
+use log::*;
+use std::collections::{HashMap, HashSet};
+
+use screeps::{
+    constants::{find, StructureType, MAX_CONSTRUCTION_SITES},
+    game,
+    local::RoomXY,
+    objects::{Room, Structure},
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    planner::{Blueprint, PlannedStructure},
+    ShardState,
+};
+
+// the order structures get built in, lowest first: whatever gets spawning and defense going
+// comes before the economy, and roads and ramparts wait until everything else is underway
+pub fn construction_priority(structure_type: StructureType) -> u8 {
+    match structure_type {
+        StructureType::Spawn => 0,
+        StructureType::Extension => 1,
+        StructureType::Tower => 2,
+        StructureType::Storage => 3,
+        StructureType::Container => 4,
+        StructureType::Link => 5,
+        StructureType::Terminal => 6,
+        StructureType::Extractor => 7,
+        StructureType::Lab => 8,
+        StructureType::Road => 20,
+        StructureType::Rampart => 21,
+        StructureType::Wall => 22,
+        _ => 10,
+    }
+}
+
+// place sites for anything in each colony's blueprint that's now allowed but not yet built,
+// whenever the room's controller level changes or it's been a while since the last check
+pub fn place_construction_sites(shard_state: &mut ShardState) {
+    let tick = game::time();
+    let mut sites_remaining =
+        MAX_CONSTRUCTION_SITES.saturating_sub(game::construction_sites().values().count() as u32);
+
+    // colonies in a fixed order, so the same ones don't always miss out when we're near the
+    // site limit
+    let mut room_names: Vec<_> = shard_state.colony_state.keys().copied().collect();
+    room_names.sort_by_key(|room_name| room_name.to_string());
+
+    for room_name in room_names {
+        let colony_state = match shard_state.colony_state.get_mut(&room_name) {
+            Some(colony_state) => colony_state,
+            None => continue,
+        };
+        let room = match game::rooms().get(room_name) {
+            Some(room) => room,
+            None => continue,
+        };
+        let rcl = match room.controller() {
+            Some(controller) if controller.my() => controller.level(),
+            _ => continue,
+        };
+
+        if rcl == colony_state.construction_rcl && tick < colony_state.next_construction_check {
+            continue;
+        }
+
+        let blueprint = match colony_state.blueprint.as_ref() {
+            Some(blueprint) => blueprint,
+            None => continue,
+        };
+
+        if sites_remaining == 0 {
+            // try again next tick, once some sites have been finished
+            debug!(
+                "at the construction site limit, can't place sites in {}",
+                room_name
+            );
+            continue;
+        }
+
+        // roads nobody walks any more aren't worth rebuilding once they've decayed away
+        let unused_roads = &colony_state.unused_roads;
+        let planned = blueprint.structures_for_rcl(rcl).filter(|structure| {
+            structure.structure_type != StructureType::Road || !unused_roads.contains(&structure.xy)
+        });
+        let placed = place_room_sites(&room, rcl, planned, sites_remaining);
+        sites_remaining = sites_remaining.saturating_sub(placed);
+
+        colony_state.construction_rcl = rcl;
+        colony_state.next_construction_check = tick + CONSTRUCTION_CHECK_INTERVAL;
+    }
+}
+
+// place sites in the room for the planned structures that are missing, in priority order, up
+// to the room's controller limits and the given number of sites; returns how many were placed
+fn place_room_sites<'a>(
+    room: &Room,
+    rcl: u8,
+    planned: impl Iterator<Item = &'a PlannedStructure>,
+    max_sites: u32,
+) -> u32 {
+    // what's already on each tile, and how many of each type there are, counting sites
+    // alongside the finished structures
+    let mut existing: HashSet<(RoomXY, StructureType)> = HashSet::new();
+    let mut occupied: HashSet<RoomXY> = HashSet::new();
+    let mut counts: HashMap<StructureType, u32> = HashMap::new();
+    for structure in room.find(find::STRUCTURES, None) {
+        let structure_type = structure.structure_type();
+        let xy = structure.pos().xy();
+        existing.insert((xy, structure_type));
+        // ramparts can share a tile with anything
+        if structure_type != StructureType::Rampart {
+            occupied.insert(xy);
+        }
+        *counts.entry(structure_type).or_insert(0) += 1;
+    }
+    for csite in room.find(find::CONSTRUCTION_SITES, None) {
+        let xy = csite.pos().xy();
+        existing.insert((xy, csite.structure_type()));
+        if csite.structure_type() != StructureType::Rampart {
+            occupied.insert(xy);
+        }
+        *counts.entry(csite.structure_type()).or_insert(0) += 1;
+    }
+
+    let mut missing: Vec<&PlannedStructure> = planned
+        .filter(|structure| !existing.contains(&(structure.xy, structure.structure_type)))
+        .collect();
+    missing.sort_by_key(|structure| {
+        (
+            construction_priority(structure.structure_type),
+            structure.rcl,
+        )
+    });
+
+    let mut placed = 0;
+    for structure in missing {
+        if placed >= max_sites {
+            break;
+        }
+
+        let count = counts.entry(structure.structure_type).or_insert(0);
+        if *count >= structure.structure_type.controller_structures(rcl as u32) {
+            continue;
+        }
+
+        // something else is in the way; leave it for now rather than tearing it down
+        if structure.structure_type != StructureType::Rampart && occupied.contains(&structure.xy) {
+            continue;
+        }
+
+        match room.create_construction_site(
+            structure.xy.x.u8(),
+            structure.xy.y.u8(),
+            structure.structure_type,
+            None,
+        ) {
+            Ok(()) => {
+                *count += 1;
+                placed += 1;
+                occupied.insert(structure.xy);
+            }
+            Err(e) => debug!(
+                "couldn't place {:?} site at {} in {}: {:?}",
+                structure.structure_type,
+                structure.xy,
+                room.name(),
+                e
+            ),
+        }
+    }
+
+    if placed > 0 {
+        info!("placed {} construction sites in {}", placed, room.name());
+    }
+    placed
+}
+
+// the room's ramparts and walls that are below the hit target the blueprint sets for them,
+// along with that target; the weakest, relative to their target, come first
+pub fn perimeter_repairs(room: &Room, blueprint: &Blueprint) -> Vec<(Structure, u32)> {
+    let rcl = room.controller().map_or(0, |controller| controller.level());
+    let mut repairs: Vec<(Structure, u32)> = room
+        .find(find::STRUCTURES, None)
+        .into_iter()
+        .filter(|structure| {
+            matches!(
+                structure.structure_type(),
+                StructureType::Rampart | StructureType::Wall
+            )
+        })
+        .filter_map(|structure| {
+            let target = blueprint.hits_target(structure.pos().xy(), rcl)?;
+            let structure = structure.as_structure().clone();
+            (structure.hits() < target).then_some((structure, target))
+        })
+        .collect();
+    repairs.sort_by_key(|(structure, target)| structure.hits() as u64 * 1_000 / *target as u64);
+    repairs
+}
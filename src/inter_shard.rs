@@ -0,0 +1,109 @@
+use js_sys::JsString;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use screeps::{game, inter_shard_memory, prelude::*};
+
+use crate::{
+    constants::*, movement::PortalRegistry, task::Task, worker::WorkerReference, ShardState,
+};
+
+// a creep's orders, written to this shard's InterShardMemory as it steps through a portal
+// so that it can pick them back up on the other side
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CreepHandoff {
+    #[serde(rename = "t")]
+    tick: u32,
+    #[serde(rename = "q")]
+    task_queue: VecDeque<Task>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct InterShardData {
+    #[serde(rename = "h", default)]
+    handoffs: HashMap<String, CreepHandoff>,
+}
+
+fn read_data(raw: Option<JsString>) -> InterShardData {
+    raw.and_then(|raw| {
+        let raw: String = raw.into();
+        match serde_json::from_str(&raw) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("couldn't parse inter-shard memory: {:?}", e);
+                None
+            }
+        }
+    })
+    .unwrap_or_default()
+}
+
+// write the task queues of creeps about to step into an inter-shard portal, and drop any
+// handoffs old enough that the creep must have already picked them up (or died)
+pub fn write_handoffs(shard_state: &ShardState) {
+    // nothing to hand off or tidy up unless someone's on their way out, so don't pay for
+    // reading the memory every tick
+    let heading_out = shard_state.worker_state.values().any(|worker_state| {
+        matches!(
+            worker_state.task_queue.front(),
+            Some(Task::MoveThroughPortal(_))
+        )
+    });
+    if !heading_out {
+        return;
+    }
+
+    let tick = game::time();
+    let mut new_handoffs = vec![];
+
+    for worker_state in shard_state.worker_state.values() {
+        if let (Some(Task::MoveThroughPortal(portal_pos)), Some(WorkerReference::Creep(creep))) = (
+            worker_state.task_queue.front(),
+            worker_state.worker_reference.as_ref(),
+        ) {
+            if creep.pos().get_range_to(*portal_pos) <= 1
+                && shard_state.portals.destination_shard(*portal_pos).is_some()
+            {
+                // everything after the portal task is what it should carry on with
+                let task_queue = worker_state.task_queue.iter().skip(1).copied().collect();
+                new_handoffs.push((creep.name(), CreepHandoff { tick, task_queue }));
+            }
+        }
+    }
+
+    let mut data = read_data(inter_shard_memory::get_local());
+    let previous_count = data.handoffs.len();
+    data.handoffs
+        .retain(|_, handoff| tick.saturating_sub(handoff.tick) < INTER_SHARD_HANDOFF_TTL);
+
+    if new_handoffs.is_empty() && data.handoffs.len() == previous_count {
+        return;
+    }
+
+    data.handoffs.extend(new_handoffs);
+    match serde_json::to_string(&data) {
+        Ok(serialized) => inter_shard_memory::set_local(&JsString::from(serialized)),
+        Err(e) => warn!("couldn't serialize inter-shard memory: {:?}", e),
+    }
+}
+
+// look for a handoff left for this creep by another shard, checking the shards our known
+// portals lead to
+pub fn take_handoff(creep_name: &str, portals: &PortalRegistry) -> Option<VecDeque<Task>> {
+    let current_shard = game::shard::name();
+
+    for shard in portals.known_shards() {
+        if shard == current_shard {
+            continue;
+        }
+
+        let mut data = read_data(inter_shard_memory::get_remote(&JsString::from(shard)));
+        if let Some(handoff) = data.handoffs.remove(creep_name) {
+            info!("{} arrived from {} with its orders", creep_name, shard);
+            return Some(handoff.task_queue);
+        }
+    }
+
+    None
+}
@@ -3,35 +3,187 @@ use log::*;
 use screeps::{
     game,
     local::{Position, RawObjectId, RoomCoordinate, RoomName},
+    raw_memory, HasPosition,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
+mod body;
+mod census;
+mod colony;
+mod construction;
+mod inter_shard;
 mod logging;
+mod logistics;
 mod movement;
+mod planner;
+mod reservation;
 mod role;
 mod task;
 mod worker;
 
 use self::{
-    role::WorkerRole,
+    census::RoleCensus,
+    colony::{ColonyState, SavedColonies},
+    constants::*,
+    movement::{Heatmap, KnownPortalDestination, PathCache, PortalRegistry},
+    reservation::ReservationLedger,
     task::Task,
     worker::{WorkerId, WorkerState},
 };
 
 /// Tunable important numbers for the bot, in one place for convenience
 mod constants {
+    use crate::body::BodySpec;
     use screeps::constants::{Part::*, *};
-    /// Won't do pathing for moving creeps if current-tick CPU spend is above this level when movement step is reached
+    /// Won't do pathing for moving creeps once current-tick CPU spend is above this level
     pub const HIGH_CPU_THRESHOLD: f64 = 250.;
-    /// Won't do pathing for moving creeps if bucket is below this number
+    /// Use the reduced pathfinding budget if bucket is below this number
     pub const LOW_BUCKET_THRESHOLD: i32 = 1_000;
+    /// CPU to spend finding paths each tick before moving remaining creeps the cheap way
+    pub const PATH_CPU_BUDGET: f64 = 20.;
+    /// CPU to spend finding paths each tick when the bucket is low
+    pub const LOW_BUCKET_PATH_CPU_BUDGET: f64 = 3.;
+    /// Each tick a worker waits for a path bumps its place in the queue by this much
+    pub const PATH_PRIORITY_PER_WAITED_TICK: u32 = 10;
     /// Consider creeps to be stuck and get them a new path after this many ticks
     pub const STUCK_REPATH_THRESHOLD: u8 = 10;
+    /// When stuck, look for a detour back onto the existing path starting this many steps ahead
+    pub const LOCAL_REPATH_MIN_REJOIN_STEPS: usize = 1;
+    /// When stuck, consider rejoining the existing path at up to this many steps
+    pub const LOCAL_REPATH_MAX_REJOIN_STEPS: usize = 8;
+    /// Limit for pathfinder ops when searching for a detour back onto the existing path
+    pub const LOCAL_REPATH_MAX_OPS: u32 = 500;
+    /// Discard detours longer than this, doing a full search instead
+    pub const LOCAL_REPATH_MAX_DETOUR_STEPS: usize = 12;
     /// Limit for pathfinder ops
     pub const MAX_OPS: u32 = 100_000;
     /// Limit for pathfinder rooms
     pub const MAX_ROOMS: u8 = 64;
+    /// Size of the square areas creeps must start from within to share a cached path
+    pub const PATH_CACHE_AREA_SIZE: u8 = 5;
+    /// Discard cached paths after this many ticks, even if they're still in use
+    pub const PATH_CACHE_TTL: u32 = 1_500;
+    /// Estimated heap usage of cached paths to allow before evicting the least recently used
+    pub const PATH_CACHE_MAX_BYTES: usize = 256 * 1024;
+    /// Check rooms for structure changes that invalidate cached paths this often
+    pub const PATH_CACHE_INVALIDATION_INTERVAL: u32 = 25;
+    /// Scan visible rooms for portals this often
+    pub const PORTAL_SCAN_INTERVAL: u32 = 100;
+    /// Forget about portals we haven't seen for this long, since they may have decayed
+    pub const PORTAL_FORGET_TICKS: u32 = 10_000;
+    /// Only route through a portal if it cuts at least this many tiles off the linear distance
+    pub const PORTAL_MIN_SAVINGS: u32 = 50;
+    /// Keep creep handoffs in InterShardMemory for this long after the creep steps into a portal
+    pub const INTER_SHARD_HANDOFF_TTL: u32 = 100;
+    /// Players whose public ramparts we'll path across, and whose creeps and towers we don't avoid
+    pub const ALLIES: &[&str] = &[];
+    /// Cautious creeps add a point of path cost per this much damage a tile is exposed to
+    pub const DANGER_CAUTIOUS_DAMAGE_PER_COST: u32 = 20;
+    /// Creeps avoiding danger add a point of path cost per this much damage a tile is exposed to
+    pub const DANGER_AVOID_DAMAGE_PER_COST: u32 = 4;
+    /// Rough damage per tick a source keeper deals to creeps next to it
+    pub const KEEPER_DAMAGE: u32 = 300;
+    /// Tiles this close to a keeper lair are where its keeper usually is
+    pub const KEEPER_LAIR_INNER_RANGE: u8 = 3;
+    /// Tiles this close to a keeper lair may be reached by its keeper
+    pub const KEEPER_LAIR_OUTER_RANGE: u8 = 5;
+    /// Halve the counts of creep steps on each tile this often
+    pub const HEATMAP_DECAY_INTERVAL: u32 = 3_000;
+    /// Memory segment to keep the heatmap in across global resets, if any
+    pub const HEATMAP_SEGMENT: Option<u8> = Some(10);
+    /// Memory segment to watch for blueprints to import, if any
+    pub const BLUEPRINT_SEGMENT: Option<u8> = Some(11);
+    /// Memory segment to keep colonies' committed blueprints in across global resets, if any
+    pub const COLONY_SEGMENT: Option<u8> = Some(12);
+    /// Save the heatmap to its segment this often
+    pub const HEATMAP_SAVE_INTERVAL: u32 = 500;
+    /// Stop adding rooms to the heatmap's segment past this size
+    pub const HEATMAP_SEGMENT_MAX_BYTES: usize = 100 * 1024;
+    /// Idle creeps park at this many tiles per colony
+    pub const PARKING_TILE_COUNT: usize = 10;
+    /// Keep parking tiles further than this from sources and minerals
+    pub const PARKING_SOURCE_CLEARANCE: u32 = 2;
+    /// Keep parking tiles further than this from spawns, so their exits stay clear
+    pub const PARKING_SPAWN_CLEARANCE: u32 = 2;
+    /// Keep parking tiles further than this from the controller, out of upgraders' way
+    pub const PARKING_CONTROLLER_CLEARANCE: u32 = 3;
+    /// Keep parking tiles at least this far in from the room edges
+    pub const PARKING_EDGE_CLEARANCE: u8 = 3;
+    /// Don't park on tiles with at least this much traffic on the heatmap
+    pub const PARKING_MAX_HEAT: u8 = 8;
+    /// Keep planned base structures at least this far in from the room edges
+    pub const PLANNER_EDGE_MARGIN: u8 = 3;
+    /// Leave tiles within this range of sources and minerals free of base structures
+    pub const PLANNER_SOURCE_CLEARANCE: u8 = 1;
+    /// Leave tiles within this range of the controller free of base structures
+    pub const PLANNER_CONTROLLER_CLEARANCE: u8 = 2;
+    /// Check colonies' blueprints for missing construction sites this often, or whenever
+    /// their controller level changes
+    pub const CONSTRUCTION_CHECK_INTERVAL: u32 = 300;
+    /// Keep hostiles at least this far from the base's structures, out of ranged attack range
+    pub const RAMPART_MARGIN: u8 = 3;
+    /// Wall in the controller along with the base
+    pub const RAMPARTS_PROTECT_CONTROLLER: bool = true;
+    /// Wall in sources along with the base
+    pub const RAMPARTS_PROTECT_SOURCES: bool = false;
+    /// Controller level to start building the perimeter, once there's energy to spare for it
+    pub const RAMPART_RCL: u8 = 4;
+    /// Hits to keep ramparts and walls at, by controller level
+    pub const RAMPART_HITS_BY_RCL: [u32; 9] = [
+        0, 0, 10_000, 30_000, 100_000, 300_000, 1_000_000, 3_000_000, 10_000_000,
+    ];
+    /// Ramparts and walls out of the towers' effective range get this many times the hits
+    pub const RAMPART_EXPOSED_HITS_MULTIPLIER: u32 = 2;
+    /// Repair ramparts and walls ahead of anything else while they're below this many hits
+    pub const RAMPART_URGENT_HITS: u32 = 10_000;
+    /// Towers only repair while they have more than this much energy, keeping the rest to fight
+    pub const TOWER_REPAIR_ENERGY_RESERVE: u32 = 500;
+    /// Towers with nothing to do look again after this many ticks
+    pub const TOWER_IDLE_TICKS: u32 = 1;
+    /// Plan the controller link at this range from the controller, in reach of upgraders
+    pub const CONTROLLER_LINK_RANGE: u8 = 2;
+    /// Controller level to build the road out to the controller
+    pub const CONTROLLER_ROAD_RCL: u8 = 2;
+    /// Controller level to build the roads out to sources
+    pub const SOURCE_ROAD_RCL: u8 = 2;
+    /// Controller level to build the mineral's road, at the same time as the extractor
+    pub const MINERAL_RCL: u8 = 6;
+    /// Controller level to build the roads for the labs, with the first of them
+    pub const LAB_RCL: u8 = 6;
+    /// Tiles with at least this much traffic on the heatmap are cheaper to route roads over
+    pub const ROAD_PLANNER_BUSY_HEAT: u8 = 8;
+    /// Roads with less than this much traffic on the heatmap are left to decay
+    pub const ROAD_MAINTENANCE_MIN_HEAT: u8 = 1;
+    /// Controller level to build the roads out to remote rooms' exits
+    pub const REMOTE_ROAD_RCL: u8 = 3;
+    /// Harvesters repair their container once it's below this percentage of its max hits
+    pub const HARVESTER_CONTAINER_REPAIR_PERCENT: u32 = 80;
+    /// Static harvesters step back from their source this often to check whether they should
+    /// be renewed or recycled
+    pub const HARVESTER_LIFECYCLE_CHECK_INTERVAL: u32 = 100;
+    /// Source harvesters carry enough to tend their container, with up to the five work parts
+    /// that empty a source just as it regenerates
+    pub const SOURCE_HARVESTER_BODY: BodySpec = BodySpec {
+        base: &[Carry],
+        unit: &[Work],
+        min_units: 1,
+        max_units: 5,
+    };
+    /// Links within this range of a source are filled by its harvester
+    pub const LINK_SOURCE_RANGE: u32 = 2;
+    /// Links within this range of the controller feed upgraders
+    pub const LINK_CONTROLLER_RANGE: u32 = 3;
+    /// Links within this range of storage are the storage link
+    pub const LINK_STORAGE_RANGE: u32 = 2;
+    /// Source and storage links send once they have at least this much energy
+    pub const LINK_SEND_THRESHOLD: u32 = 400;
+    /// Send to the controller link whenever it has less than this much energy
+    pub const LINK_CONTROLLER_TOP_UP: u32 = 400;
+    /// Links with nothing to send look again after this many ticks
+    pub const LINK_IDLE_TICKS: u32 = 5;
+    /// Upgraders within this range of the controller ask for energy to be brought to them
+    pub const UPGRADER_REQUEST_RANGE: u32 = 3;
     /// A* heuristic weight - default is 1.2, but it risks non-optimal paths, so we turn it down a bit
     pub const HEURISTIC_WEIGHT: f64 = 1.0;
     /// When task finding fails, idle this long
@@ -40,14 +192,29 @@ mod constants {
     pub const BUILDER_ENERGY_PICKUP_THRESHOLD: u32 = 100;
     /// Builder role considers energy for withdraw from structures above this amount
     pub const BUILDER_ENERGY_WITHDRAW_THRESHOLD: u32 = 1_000;
-    /// Cost of each set of hauler body parts (2 carry, 1 move)
-    pub const HAULER_COST_PER_MULTIPLIER: u32 = Carry.cost() * 2 + Move.cost();
-    /// Count of parts in the hauler body set
-    pub const HAULER_PARTS_PER_MULTIPLIER: u32 = 3;
+    /// Builder bodies are work and carry in equal measure
+    pub const BUILDER_BODY: BodySpec = BodySpec {
+        base: &[],
+        unit: &[Work, Carry],
+        min_units: 1,
+        max_units: 8,
+    };
+    /// Startup creeps do a bit of everything, kept small since they're only around early on
+    pub const STARTUP_BODY: BodySpec = BodySpec {
+        base: &[],
+        unit: &[Work, Carry],
+        min_units: 1,
+        max_units: 4,
+    };
     /// How many haulers to try to keep alive in each room
     pub const HAULER_COUNT_TARGET: u8 = 1;
-    /// Largest number of sets to allow a hauler to be spawned with
-    pub const HAULER_MAX_MULTIPLIER: u32 = MAX_CREEP_SIZE / HAULER_PARTS_PER_MULTIPLIER;
+    /// Hauler bodies are pairs of carry parts, as many as the room can afford
+    pub const HAULER_BODY: BodySpec = BodySpec {
+        base: &[],
+        unit: &[Carry, Carry],
+        min_units: 1,
+        max_units: MAX_CREEP_SIZE,
+    };
     /// Hauler role considers energy on the ground for grabbing above this amount
     pub const HAULER_ENERGY_PICKUP_THRESHOLD: u32 = 35;
     /// Hauler role considers energy for withdraw from structures above this amount
@@ -70,14 +237,49 @@ mod constants {
     pub const REPAIR_WATERMARK_RCL_8: u32 = 3_000_000;
     /// How many do-it-all creeps to keep alive at RCL 1
     pub const STARTUP_RCL1_COUNT_TARGET: u8 = 15;
+    /// How many do-it-all creeps to spawn from whatever energy's left when a colony's economy
+    /// has collapsed
+    pub const STARTUP_RECOVERY_COUNT_TARGET: u8 = 2;
     /// How many upgraders to try to keep alive in each room
     pub const UPGRADER_COUNT_TARGET: u8 = 4;
     /// Builder role considers energy on the groundfor grabbing above this amount
     pub const UPGRADER_ENERGY_PICKUP_THRESHOLD: u32 = 100;
     /// Upgrader roler considers energy for withdraw from structures above this amount
     pub const UPGRADER_ENERGY_WITHDRAW_THRESHOLD: u32 = 1_200;
+    /// Upgrader bodies lean on work parts, since energy's brought to them
+    pub const UPGRADER_BODY: BodySpec = BodySpec {
+        base: &[Carry],
+        unit: &[Work],
+        min_units: 1,
+        max_units: 15,
+    };
     /// Fill terminals to this much energy
     pub const TERMINAL_ENERGY_TARGET: u32 = 50_000;
+    /// Creeps retreat from nearby hostiles when their hits are below 1/this of their max
+    pub const RETREAT_HITS_DIVISOR: u32 = 2;
+    /// Look for hostiles within this range when deciding whether and where to retreat
+    pub const RETREAT_SEARCH_RANGE: u8 = 8;
+    /// Retreating creeps try to keep at least this far from hostiles with ranged attack parts
+    pub const RETREAT_RANGED_RANGE: u32 = (CREEP_RANGED_ACTION_RANGE + 2) as u32;
+    /// Retreating creeps try to keep at least this far from hostiles with melee attack parts
+    pub const RETREAT_MELEE_RANGE: u32 = 3;
+    /// How long a retreat lasts before the creep goes back to what it was doing
+    pub const RETREAT_TICKS: u32 = 20;
+    /// How long a retreat ordered through the console or client lasts
+    pub const MANUAL_RETREAT_TICKS: u32 = 50;
+    /// Creeps with bodies at least this big are renewed at a spawn rather than replaced
+    pub const RENEW_MIN_BODY_SIZE: usize = 24;
+    /// Creeps worth renewing head to a spawn once they have fewer ticks to live than this
+    pub const RENEW_BELOW_TTL: u32 = 300;
+    /// Renewal carries on until the creep has this many ticks to live
+    pub const RENEW_TARGET_TTL: u32 = 1_200;
+    /// Creeps with bodies smaller than the full size for their role divided by this are
+    /// recycled, once the colony's back on its feet
+    pub const UNDERSIZED_BODY_DIVISOR: usize = 2;
+    /// Creeps start getting clear of a nuke landing spot this many ticks before impact
+    pub const NUKE_FLEE_TICKS: u32 = 50;
+    /// Distance to keep from a nuke landing spot, just outside the blast radius
+    pub const NUKE_FLEE_RANGE: u32 = 3;
     /// Creeps are just out of range of their ranged action at this range; at this range
     /// they'll usually path avoiding creeps
     pub const RANGED_OUT_OF_RANGE: u32 = (CREEP_RANGED_ACTION_RANGE + 1) as u32;
@@ -90,6 +292,16 @@ mod constants {
 // this is an alternative to keeping state in memory on game objects - but will be lost on
 // global resets, which occur at differing frequencies on different server environments
 static mut SHARD_STATE: Option<ShardState> = None;
+
+// get at the shard state, setting it up if this is the first use since the global reset
+//
+// SAFETY: the game loop and the console functions each run to completion on the one thread,
+// never overlapping, so only one of these references is in use at a time; callers must not
+// hold on to it past the end of their call
+unsafe fn shard_state() -> &'static mut ShardState {
+    (*std::ptr::addr_of_mut!(SHARD_STATE)).get_or_insert_with(ShardState::default)
+}
+
 static INIT_LOGGING: std::sync::Once = std::sync::Once::new();
 
 // define the giant struct which holds all of state data we're interested in holding
@@ -99,11 +311,23 @@ pub struct ShardState {
     pub global_init_time: u32,
     // owned room states and spawn queues
     pub colony_state: HashMap<RoomName, ColonyState>,
+    // what's kept of each colony across global resets
+    pub saved_colonies: SavedColonies,
     // workers and their task queues (includes creeps as well as structures)
     pub worker_state: HashMap<WorkerId, WorkerState>,
-    // additionally, a HashSet<WorkerRole> where we'll mark which roles
-    // we have active workers for, allowing spawns to check which workers to create
-    pub worker_roles: HashSet<WorkerRole>,
+    // additionally, the creeps filling each role, allowing spawns to check which workers to
+    // create or replace
+    pub census: RoleCensus,
+    // paths found for recent movement, shared between creeps heading to the same destination
+    pub path_cache: PathCache,
+    // portals we've seen, for routing through them and for travel to other shards
+    pub portals: PortalRegistry,
+    // where creeps have been walking, for deciding where roads are worth having
+    pub heatmap: Heatmap,
+    // memory segments wanted active next tick, collected from everything that uses them
+    pub requested_segments: Vec<u8>,
+    // what workers are on their way to take from or bring to each target
+    pub reservations: ReservationLedger,
 }
 
 impl Default for ShardState {
@@ -111,16 +335,18 @@ impl Default for ShardState {
         ShardState {
             global_init_time: game::time(),
             colony_state: HashMap::new(),
+            saved_colonies: SavedColonies::default(),
             worker_state: HashMap::new(),
-            worker_roles: HashSet::new(),
+            census: RoleCensus::default(),
+            path_cache: PathCache::default(),
+            portals: PortalRegistry::default(),
+            heatmap: Heatmap::default(),
+            requested_segments: Vec::new(),
+            reservations: ReservationLedger::default(),
         }
     }
 }
 
-pub struct ColonyState {
-    // todo add stuff here - spawn queue, maybe remote tracking
-}
-
 #[wasm_bindgen]
 pub fn update_selected_object(client_tick: u32, object_id: JsString, object_type: String) {
     let raw_obj: RawObjectId = object_id.try_into().unwrap();
@@ -146,12 +372,187 @@ pub fn right_click_position(
     info!("click observed: {}, {} {}", pos, object_id, object_type);
 
     if object_type == "creep" {
-        let shard_state = unsafe { SHARD_STATE.get_or_insert_with(ShardState::default) };
+        let shard_state = unsafe { shard_state() };
         let id_raw: RawObjectId = object_id.try_into().unwrap();
+        // clicking on a portal means go through it, as opposed to just stepping next to it;
+        // for one to another room on this shard, that's heading for where it lands
+        let task = match shard_state
+            .portals
+            .get(pos)
+            .map(|portal| &portal.destination)
+        {
+            Some(KnownPortalDestination::Shard(..)) => Task::MoveThroughPortal(pos),
+            Some(KnownPortalDestination::Room(landing)) => Task::MoveToPosition(*landing, 0),
+            None => Task::MoveToPosition(pos, 0),
+        };
         shard_state
             .worker_state
             .entry(WorkerId::Creep(id_raw.into()))
-            .and_modify(|state| state.task_queue.push_front(Task::MoveToPosition(pos, 0)));
+            .and_modify(|state| state.task_queue.push_front(task));
+    }
+}
+
+#[wasm_bindgen]
+pub fn send_creep_to_shard(object_id: JsString, shard: String) {
+    let shard_state = unsafe { shard_state() };
+    let id_raw: RawObjectId = object_id.try_into().unwrap();
+    let id = WorkerId::Creep(id_raw.into());
+
+    let creep_pos = match id.resolve() {
+        Some(worker_reference) => worker_reference.pos(),
+        None => {
+            warn!("couldn't find creep {} to send to {}", id_raw, shard);
+            return;
+        }
+    };
+
+    match shard_state.portals.closest_to_shard(creep_pos, &shard) {
+        Some(portal_pos) => {
+            info!(
+                "sending {} to {} via portal at {}",
+                id_raw, shard, portal_pos
+            );
+            shard_state.worker_state.entry(id).and_modify(|state| {
+                state
+                    .task_queue
+                    .push_front(Task::MoveThroughPortal(portal_pos))
+            });
+        }
+        None => warn!("no known portal to {}", shard),
+    }
+}
+
+#[wasm_bindgen]
+pub fn retreat_creep(object_id: JsString) {
+    let shard_state = unsafe { shard_state() };
+    let id_raw: RawObjectId = object_id.try_into().unwrap();
+    let id = WorkerId::Creep(id_raw.into());
+    info!("retreat ordered: {}", id_raw);
+
+    // fall back to the creep's parking tile, or the nearest one any colony has, or failing
+    // that home at the nearest of our spawns, even when there's no threat in sight
+    let fallback = id.resolve().and_then(|worker_reference| {
+        let creep_pos = worker_reference.pos();
+        shard_state
+            .worker_state
+            .get(&id)
+            .and_then(|state| state.parking)
+            .or_else(|| {
+                shard_state
+                    .colony_state
+                    .values()
+                    .flat_map(|colony_state| colony_state.parking.iter())
+                    .min_by_key(|pos| creep_pos.get_range_to(**pos))
+                    .copied()
+            })
+            .or_else(|| {
+                game::spawns()
+                    .values()
+                    .map(|spawn| spawn.pos())
+                    .min_by_key(|pos| creep_pos.get_range_to(*pos))
+            })
+    });
+    shard_state.worker_state.entry(id).and_modify(|state| {
+        state
+            .task_queue
+            .push_front(Task::Retreat(game::time() + MANUAL_RETREAT_TICKS, fallback))
+    });
+}
+
+#[wasm_bindgen]
+pub fn export_blueprint(room_name: JsString) -> Option<String> {
+    let room_name = RoomName::try_from(room_name).unwrap();
+    match game::rooms().get(room_name) {
+        Some(room) => {
+            planner::blueprint_from_room(&room).map(|blueprint| planner::to_buildings(&blueprint))
+        }
+        None => {
+            warn!("can't see {} to export its blueprint", room_name);
+            None
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn import_blueprint(room_name: JsString, blueprint: String) {
+    let shard_state = unsafe { shard_state() };
+    let room_name = RoomName::try_from(room_name).unwrap();
+    match shard_state.colony_state.get_mut(&room_name) {
+        Some(colony_state) => match colony_state.import_blueprint(room_name, &blueprint) {
+            Ok(count) => info!(
+                "imported blueprint with {} structures for {}, previewing until committed",
+                count, room_name
+            ),
+            Err(e) => warn!("bad blueprint for {}: {}", room_name, e),
+        },
+        None => warn!("{} isn't a colony", room_name),
+    }
+}
+
+#[wasm_bindgen]
+pub fn commit_blueprint(room_name: JsString) {
+    let shard_state = unsafe { shard_state() };
+    let room_name = RoomName::try_from(room_name).unwrap();
+    match shard_state.colony_state.get_mut(&room_name) {
+        Some(colony_state) => {
+            if colony_state.commit_blueprint() {
+                if let Some(blueprint) = colony_state.blueprint.as_ref() {
+                    shard_state
+                        .saved_colonies
+                        .save_blueprint(room_name, blueprint);
+                }
+                info!("committed imported blueprint for {}", room_name);
+            } else {
+                warn!("no imported blueprint to commit for {}", room_name);
+            }
+        }
+        None => warn!("{} isn't a colony", room_name),
+    }
+}
+
+#[wasm_bindgen]
+pub fn discard_blueprint(room_name: JsString) {
+    let shard_state = unsafe { shard_state() };
+    let room_name = RoomName::try_from(room_name).unwrap();
+    if let Some(colony_state) = shard_state.colony_state.get_mut(&room_name) {
+        colony_state.pending_blueprint = None;
+    }
+}
+
+#[wasm_bindgen]
+pub fn census() {
+    let shard_state = unsafe { shard_state() };
+    for line in shard_state.census.report(game::time()) {
+        info!("{}", line);
+    }
+}
+
+#[wasm_bindgen]
+pub fn add_remote(room_name: JsString, remote: JsString) {
+    let shard_state = unsafe { shard_state() };
+    let room_name = RoomName::try_from(room_name).unwrap();
+    let remote = RoomName::try_from(remote).unwrap();
+    let room = match game::rooms().get(room_name) {
+        Some(room) => room,
+        None => {
+            warn!("can't see {} to add a remote to it", room_name);
+            return;
+        }
+    };
+    match shard_state.colony_state.get_mut(&room_name) {
+        Some(colony_state) => match colony_state.add_remote(&room, remote, &shard_state.heatmap) {
+            Ok(road_count) => {
+                shard_state
+                    .saved_colonies
+                    .save_remotes(room_name, colony_state);
+                info!(
+                    "added {} as a remote of {}, with {} tiles of new road",
+                    remote, room_name, road_count
+                )
+            }
+            Err(e) => warn!("couldn't add remote {} to {}: {}", remote, room_name, e),
+        },
+        None => warn!("{} isn't a colony", room_name),
     }
 }
 
@@ -168,17 +569,34 @@ pub fn wasm_loop() {
     // SAFETY: only one instance of the game loop can be running at a time
     // We must use this same mutable reference throughout the entire tick,
     // as any other access to it would cause undefined behavior!
-    let shard_state = unsafe { SHARD_STATE.get_or_insert_with(ShardState::default) };
+    let shard_state = unsafe { shard_state() };
 
     // register all creeps that aren't yet in our tracking, and delete the state of any that we can
     // no longer see
     worker::scan_and_register_creeps(shard_state);
 
+    // look for portals in the rooms we can see
+    if tick.is_multiple_of(PORTAL_SCAN_INTERVAL) || tick == shard_state.global_init_time {
+        shard_state.portals.scan_visible_rooms();
+    }
+
+    // read what was saved of the colonies before the reset, and save any changes to it since
+    let colonies_loaded = shard_state
+        .saved_colonies
+        .sync_segment(COLONY_SEGMENT, &mut shard_state.requested_segments);
+
     // scan for new worker structures as well - every 100 ticks, or if this is the startup tick
-    if tick % 100 == 0 || tick == shard_state.global_init_time {
+    // or the first we can set up colonies on
+    if tick.is_multiple_of(100) || tick == shard_state.global_init_time || colonies_loaded {
         worker::scan_and_register_structures(shard_state);
     }
 
+    // place construction sites for whatever's missing from each colony's blueprint
+    construction::place_construction_sites(shard_state);
+
+    // gather up what each colony has to give and wants, for haulers to pick from
+    logistics::post_colony_logistics(shard_state);
+
     // run all registered workers, attempting to resolve those that haven't already and deleting
     // any workers that don't resolve
 
@@ -188,11 +606,51 @@ pub fn wasm_loop() {
     // spawn started this tick
     worker::run_workers(shard_state);
 
+    // leave orders for creeps about to cross to another shard where they can find them
+    inter_shard::write_handoffs(shard_state);
+
+    // drop any cached paths through rooms whose structures have changed
+    if tick.is_multiple_of(PATH_CACHE_INVALIDATION_INTERVAL) {
+        shard_state.path_cache.check_for_invalidation();
+    }
+
+    // fade out old traffic on the heatmap, and keep its saved copy up to date
+    if tick.is_multiple_of(HEATMAP_DECAY_INTERVAL) {
+        shard_state.heatmap.decay();
+    }
+    if let Some(segment) = HEATMAP_SEGMENT {
+        shard_state
+            .heatmap
+            .sync_segment(segment, tick, &mut shard_state.requested_segments);
+    }
+
+    // pick up any blueprints left in their segment
+    if let Some(segment) = BLUEPRINT_SEGMENT {
+        colony::import_blueprints_from_segment(shard_state, segment);
+    }
+
     // run movement phase now that all workers have run, while deleting the references to game
     // objects from the current tick (as a way to ensure they aren't used in future ticks
     // as well as to enable them to be GC'd and their memory freed in js heap, if js wants to)
     movement::run_movement_and_remove_worker_refs(shard_state);
 
+    if cfg!(feature = "heatmap-visuals") {
+        shard_state.heatmap.draw_visuals();
+    }
+
+    // show imported blueprints waiting to be committed
+    for (room_name, colony_state) in shard_state.colony_state.iter() {
+        if let Some(blueprint) = colony_state.pending_blueprint.as_ref() {
+            blueprint.draw_visuals(*room_name);
+        }
+    }
+
+    // ask for whichever memory segments were wanted this tick, to have them next tick
+    if !shard_state.requested_segments.is_empty() {
+        raw_memory::set_active_segments(&shard_state.requested_segments);
+        shard_state.requested_segments.clear();
+    }
+
     info!(
         "tick {} done! cpu: {:.4}, execution instance age {}",
         tick,
@@ -0,0 +1,296 @@
+use std::cmp::Reverse;
+
+use screeps::{
+    constants::{find, ResourceType},
+    enums::StructureObject,
+    game,
+    local::{ObjectId, Position},
+    objects::{Creep, Resource, Structure},
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    reservation::ReservationLedger,
+    role::{link_kind, LinkKind},
+    task::Task,
+    worker::Worker,
+    ShardState,
+};
+
+// how urgently a request needs filling or an offer needs collecting; higher goes first.
+// Spawns and extensions come top, since everything else waits on spawning
+pub const PRIORITY_SPAWNING: u8 = 4;
+// energy on the ground, which decays away if it's left there
+pub const PRIORITY_DROPPED: u8 = 3;
+pub const PRIORITY_TOWER: u8 = 3;
+pub const PRIORITY_WORKER: u8 = 2;
+// containers and links that fill up from the sources
+pub const PRIORITY_COLLECT: u8 = 2;
+pub const PRIORITY_TERMINAL: u8 = 1;
+// storage takes whatever's left over, and gives it back when something needs it
+pub const PRIORITY_STORE: u8 = 0;
+
+// something holding or wanting a resource, that a hauler can go to
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+pub enum LogisticsTarget {
+    Structure(ObjectId<Structure>),
+    Resource(ObjectId<Resource>),
+    Creep(ObjectId<Creep>),
+}
+
+// an amount of a resource on offer or wanted at a target
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct LogisticsEntry {
+    pub target: LogisticsTarget,
+    pub pos: Position,
+    pub resource_type: ResourceType,
+    pub amount: u32,
+    pub priority: u8,
+}
+
+impl LogisticsEntry {
+    // the task for a hauler to collect this offer
+    pub fn take_task(&self) -> Option<Task> {
+        match self.target {
+            LogisticsTarget::Structure(id) => Some(Task::TakeFromStructure(id, self.resource_type)),
+            LogisticsTarget::Resource(id) => Some(Task::TakeFromResource(id)),
+            LogisticsTarget::Creep(_) => None,
+        }
+    }
+
+    // the task for a hauler to fill this request
+    pub fn deliver_task(&self) -> Option<Task> {
+        match self.target {
+            LogisticsTarget::Structure(id) => {
+                Some(Task::DeliverToStructure(id, self.resource_type))
+            }
+            LogisticsTarget::Creep(id) => Some(Task::DeliverToCreep(id, self.resource_type)),
+            LogisticsTarget::Resource(_) => None,
+        }
+    }
+}
+
+// everything in a colony that has resources to give or wants some, posted fresh each tick,
+// for haulers to pick from
+#[derive(Default, Debug)]
+pub struct LogisticsBroker {
+    offers: Vec<LogisticsEntry>,
+    requests: Vec<LogisticsEntry>,
+}
+
+impl LogisticsBroker {
+    pub fn clear(&mut self) {
+        self.offers.clear();
+        self.requests.clear();
+    }
+
+    // offer what's left at the target once reservations on it are taken out
+    pub fn offer(&mut self, mut entry: LogisticsEntry, reservations: &ReservationLedger) {
+        entry.amount = reservations.unreserved_amount(entry.target, entry.amount);
+        if entry.amount > 0 {
+            self.offers.push(entry);
+        }
+    }
+
+    // ask for what the target still needs once reservations on it are taken out
+    pub fn request(&mut self, mut entry: LogisticsEntry, reservations: &ReservationLedger) {
+        entry.amount = reservations.unreserved_capacity(entry.target, entry.amount);
+        if entry.amount > 0 {
+            self.requests.push(entry);
+        }
+    }
+
+    // whether anything more important than the given priority still wants the resource
+    pub fn has_requests_above(&self, resource_type: ResourceType, priority: u8) -> bool {
+        self.requests
+            .iter()
+            .any(|request| request.resource_type == resource_type && request.priority > priority)
+    }
+
+    // take the most important, closest request a hauler at the given position can help with,
+    // marking what it's carrying as on the way so other haulers look elsewhere
+    pub fn claim_request(
+        &mut self,
+        from: Position,
+        resource_type: ResourceType,
+        carrying: u32,
+    ) -> Option<LogisticsEntry> {
+        claim(&mut self.requests, from, resource_type, carrying, 0)
+    }
+
+    // take the most important, closest offer of at least the given priority for a hauler at
+    // the given position with the given free capacity
+    pub fn claim_offer(
+        &mut self,
+        from: Position,
+        resource_type: ResourceType,
+        capacity: u32,
+        min_priority: u8,
+    ) -> Option<LogisticsEntry> {
+        claim(
+            &mut self.offers,
+            from,
+            resource_type,
+            capacity,
+            min_priority,
+        )
+    }
+}
+
+// pick the best entry for a hauler, preferring higher priority, then entries that fill at least
+// half its capacity, then the closest; the claimed amount is taken off the entry, which is
+// dropped once there's nothing left of it
+fn claim(
+    entries: &mut Vec<LogisticsEntry>,
+    from: Position,
+    resource_type: ResourceType,
+    capacity: u32,
+    min_priority: u8,
+) -> Option<LogisticsEntry> {
+    let index = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.resource_type == resource_type && entry.priority >= min_priority)
+        .max_by_key(|(_, entry)| {
+            let range = if entry.pos.room_name() == from.room_name() {
+                from.get_range_to(entry.pos)
+            } else {
+                u32::MAX
+            };
+            (entry.priority, entry.amount * 2 >= capacity, Reverse(range))
+        })
+        .map(|(index, _)| index)?;
+
+    let entry = &mut entries[index];
+    let claimed = entry.amount.min(capacity);
+    entry.amount -= claimed;
+    let mut claimed_entry = entry.clone();
+    claimed_entry.amount = claimed;
+    if entry.amount == 0 {
+        entries.swap_remove(index);
+    }
+    Some(claimed_entry)
+}
+
+// rebuild each colony's offers and requests from what's in the room, and whatever its
+// workers post
+pub fn post_colony_logistics(shard_state: &mut ShardState) {
+    let reservations = &shard_state.reservations;
+    for (room_name, colony_state) in shard_state.colony_state.iter_mut() {
+        let broker = &mut colony_state.logistics;
+        broker.clear();
+        let room = match game::rooms().get(*room_name) {
+            Some(room) => room,
+            None => continue,
+        };
+
+        for structure in room.find(find::STRUCTURES, None) {
+            let pos = structure.pos();
+            let target = LogisticsTarget::Structure(structure.as_structure().id());
+            let energy_entry = |amount, priority| LogisticsEntry {
+                target,
+                pos,
+                resource_type: ResourceType::Energy,
+                amount,
+                priority,
+            };
+            match &structure {
+                StructureObject::StructureSpawn(o) => broker.request(
+                    energy_entry(
+                        o.store().get_free_capacity(Some(ResourceType::Energy)) as u32,
+                        PRIORITY_SPAWNING,
+                    ),
+                    reservations,
+                ),
+                StructureObject::StructureExtension(o) => broker.request(
+                    energy_entry(
+                        o.store().get_free_capacity(Some(ResourceType::Energy)) as u32,
+                        PRIORITY_SPAWNING,
+                    ),
+                    reservations,
+                ),
+                StructureObject::StructureTower(o) => broker.request(
+                    energy_entry(
+                        o.store().get_free_capacity(Some(ResourceType::Energy)) as u32,
+                        PRIORITY_TOWER,
+                    ),
+                    reservations,
+                ),
+                StructureObject::StructureTerminal(o) => broker.request(
+                    energy_entry(
+                        TERMINAL_ENERGY_TARGET.saturating_sub(
+                            o.store().get_used_capacity(Some(ResourceType::Energy)),
+                        ),
+                        PRIORITY_TERMINAL,
+                    ),
+                    reservations,
+                ),
+                StructureObject::StructureStorage(o) => {
+                    broker.request(
+                        energy_entry(
+                            o.store().get_free_capacity(Some(ResourceType::Energy)) as u32,
+                            PRIORITY_STORE,
+                        ),
+                        reservations,
+                    );
+                    broker.offer(
+                        energy_entry(
+                            o.store().get_used_capacity(Some(ResourceType::Energy)),
+                            PRIORITY_STORE,
+                        ),
+                        reservations,
+                    );
+                }
+                StructureObject::StructureContainer(o) => {
+                    let energy = o.store().get_used_capacity(Some(ResourceType::Energy));
+                    if energy >= HAULER_ENERGY_WITHDRAW_THRESHOLD {
+                        broker.offer(energy_entry(energy, PRIORITY_COLLECT), reservations);
+                    }
+                }
+                // links that source links send their surplus to, but not the controller's
+                StructureObject::StructureLink(o)
+                    if matches!(
+                        link_kind(&room, pos),
+                        LinkKind::Storage | LinkKind::Receiver
+                    ) =>
+                {
+                    let energy = o.store().get_used_capacity(Some(ResourceType::Energy));
+                    if energy >= HAULER_ENERGY_WITHDRAW_THRESHOLD {
+                        broker.offer(energy_entry(energy, PRIORITY_COLLECT), reservations);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for resource in room.find(find::DROPPED_RESOURCES, None) {
+            if resource.amount() >= HAULER_ENERGY_PICKUP_THRESHOLD {
+                broker.offer(
+                    LogisticsEntry {
+                        target: LogisticsTarget::Resource(resource.id()),
+                        pos: resource.pos(),
+                        resource_type: resource.resource_type(),
+                        amount: resource.amount(),
+                        priority: PRIORITY_DROPPED,
+                    },
+                    reservations,
+                );
+            }
+        }
+    }
+
+    // workers post to the colony they're in
+    for worker_state in shard_state.worker_state.values() {
+        if let Some(worker) = worker_state.worker_reference.as_ref() {
+            if let Some(colony_state) = shard_state.colony_state.get_mut(&worker.pos().room_name())
+            {
+                worker_state.role.post_logistics(
+                    worker,
+                    &mut colony_state.logistics,
+                    &shard_state.reservations,
+                );
+            }
+        }
+    }
+}
@@ -10,16 +10,23 @@ use screeps::{
 
 use crate::{
     constants::*,
-    worker::{Worker, WorkerReference},
+    worker::{Worker, WorkerId, WorkerReference},
     ShardState,
 };
 
 mod callbacks;
 mod goal;
+mod heatmap;
+mod path_cache;
 mod path_state;
+mod portals;
 
+pub use callbacks::is_ally;
 pub use goal::MovementGoal;
+pub use heatmap::{Heatmap, RoomHeatmap};
+pub use path_cache::PathCache;
 pub use path_state::PathState;
+pub use portals::{KnownPortalDestination, PortalRegistry};
 
 // enum for the different speeds available to creeps
 #[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
@@ -33,6 +40,40 @@ pub enum MovementProfile {
     RoadsOneToTwo,
 }
 
+impl MovementProfile {
+    // costs of plains and swamps for the pathfinder
+    pub fn terrain_costs(&self) -> (u8, u8) {
+        match self {
+            // creep that moves at full speed over swamp, treat swamps as the same as plains
+            MovementProfile::SwampFiveToOne => (1, 1),
+            MovementProfile::PlainsOneToOne => (1, 5),
+            // double the cost of swamps and plains to allow roads to be lowest
+            MovementProfile::RoadsOneToTwo => (2, 10),
+        }
+    }
+
+    // move parts needed to keep the given number of other parts moving at this speed
+    pub fn move_parts(&self, parts: u32) -> u32 {
+        match self {
+            MovementProfile::SwampFiveToOne => parts * 5,
+            MovementProfile::PlainsOneToOne => parts,
+            MovementProfile::RoadsOneToTwo => parts.div_ceil(2),
+        }
+    }
+}
+
+// enum for how far out of the way creeps should go to keep out of reach of hostiles
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub enum DangerProfile {
+    // path straight through, for creeps that can take the hits or are headed into danger anyway
+    #[default]
+    Ignore,
+    // accept a somewhat longer path to stay out of reach
+    Cautious,
+    // go well out of the way, for creeps that are already hurt or running away
+    Avoid,
+}
+
 impl WorkerReference {
     fn move_with_path(
         &self,
@@ -96,6 +137,64 @@ impl WorkerReference {
     }
 }
 
+// find a path to the goal, going through a known portal if it's a big enough shortcut
+fn find_path(
+    movement_goal: &MovementGoal,
+    from_position: Position,
+    path_cache: &mut PathCache,
+    portals: &PortalRegistry,
+) -> PathState {
+    match portals.find_shortcut(from_position, movement_goal) {
+        Some((portal_pos, exit_pos)) => {
+            let portal_goal = MovementGoal {
+                pos: portal_pos,
+                range: 0,
+                ..movement_goal.clone()
+            };
+            let mut path_state = portal_goal.find_path_to(from_position, path_cache);
+            path_state
+                .append_through_portal(exit_pos, movement_goal.find_path_to(exit_pos, path_cache));
+            // keep the real goal, so we don't repath when we're partway there
+            path_state.goal = movement_goal.clone();
+            path_state
+        }
+        None => movement_goal.find_path_to(from_position, path_cache),
+    }
+}
+
+// a worker that needs a new path this tick, waiting for its turn at the pathfinding budget
+struct PathRequest {
+    worker_id: WorkerId,
+    worker_reference: WorkerReference,
+    position: Position,
+    movement_goal: MovementGoal,
+    // the path the worker was following when it got stuck, which we'll try to detour back
+    // onto before doing a full search
+    stuck_path: Option<PathState>,
+    priority: u32,
+}
+
+impl WorkerReference {
+    // when there's no budget left to find a path, take a step straight toward the goal (or
+    // straight away from it, if fleeing) in the hope that it's some progress
+    fn direct_step(
+        &self,
+        movement_goal: &MovementGoal,
+        current_position: Position,
+        moving_creeps: &mut HashMap<Position, Direction>,
+    ) {
+        let direction = match current_position.get_direction_to(movement_goal.pos) {
+            Some(direction) if movement_goal.flee => -direction,
+            Some(direction) => direction,
+            None => return,
+        };
+        if let WorkerReference::Creep(creep) = self {
+            let _ = creep.move_direction(direction);
+            moving_creeps.insert(current_position + direction, direction);
+        }
+    }
+}
+
 pub fn run_movement_and_remove_worker_refs(shard_state: &mut ShardState) {
     // creeps that are idle register themselves in this hashmap so that creeps
     // moving to their position can get them to swap positions as a simple
@@ -107,31 +206,28 @@ pub fn run_movement_and_remove_worker_refs(shard_state: &mut ShardState) {
     // to swap with
     let mut moving_creeps = HashMap::new();
 
-    // check if CPU is high this tick or the bucket is low, we'll skip finding new paths if so
-    let tick_cpu = game::cpu::get_used();
-    let bucket_cpu = game::cpu::bucket();
-    let cpu_critical = if tick_cpu > HIGH_CPU_THRESHOLD {
-        warn!(
-            "CPU usage high, will skip finding fresh paths: {}",
-            tick_cpu
-        );
-        true
-    } else if bucket_cpu < LOW_BUCKET_THRESHOLD {
-        warn!(
-            "CPU bucket low, will skip finding fresh paths: {}",
-            bucket_cpu
-        );
-        true
-    } else {
-        false
-    };
+    // workers that need a new path, served in priority order once everyone else has moved
+    let mut path_requests = vec![];
 
     // loop through all workers, removing their reference for use
     // during this movement step (or simply discarded in the case
     // of worker roles that can't move)
-    for worker_state in shard_state.worker_state.values_mut() {
+    for (worker_id, worker_state) in shard_state.worker_state.iter_mut() {
         // take the reference out of the worker
         if let Some(worker_reference) = worker_state.worker_reference.take() {
+            // count a step on the heatmap whenever a creep's somewhere new, whether it got
+            // there following a path, stepping directly, or being swapped with
+            if worker_state.role.can_move() {
+                let position = worker_reference.pos();
+                if worker_state
+                    .last_position
+                    .is_some_and(|last_position| last_position != position)
+                {
+                    shard_state.heatmap.record(position);
+                }
+                worker_state.last_position = Some(position);
+            }
+
             // if the worker can't move, that's all we needed to do as end-of-tick cleanup
             if worker_state.role.can_move() && worker_reference.fatigue() == 0 {
                 // it's a role that can move, let's consider it for movement
@@ -139,53 +235,58 @@ pub fn run_movement_and_remove_worker_refs(shard_state: &mut ShardState) {
                 // it can move - check if it has somewhere to be, and mark it as idle if not
                 if let Some(movement_goal) = worker_state.movement_goal.take() {
                     // we have a goal; first check if it's met
-                    if position.get_range_to(movement_goal.pos) <= movement_goal.range {
+                    if movement_goal.is_met(position) {
                         // goal is met! unset the path_state if there is one and idle
                         worker_state.path_state = None;
                         idle_creeps.insert(position, worker_reference);
-                    } else {
-                        // goal isn't met - let's see if there's a cached path that seems valid
-                        let path_needed =
-                            if let Some(mut path_state) = worker_state.path_state.take() {
-                                // first call the function that updates the current position
-                                // (or the stuck count if we didn't move)
-                                path_state.check_if_moved_and_update_pos(position);
-
-                                // check only for equality of the goal position as opposed to the whole goal
-                                // so that changes in the avoid_creeps state don't invoke a repath
-                                if path_state.goal.pos == movement_goal.pos
-                                    && path_state.stuck_count <= STUCK_REPATH_THRESHOLD
-                                {
-                                    // still has the same goal as the cached path; we're ok
-                                    // to simply move, retaining the path unless it's not returned
-                                    worker_state.path_state = worker_reference.move_with_path(
-                                        path_state,
-                                        position,
-                                        &mut moving_creeps,
-                                    );
-                                    false
-                                } else {
-                                    // the goal has changed or we're stuck - mark pathing as needed!
-                                    true
-                                }
-                            } else {
-                                // no cached path found, mark as needed
-                                true
-                            };
-
-                        // if we need to path and we're in a CPU state to do it, do so
-                        if path_needed && !cpu_critical {
-                            let path_state = movement_goal.find_path_to(position);
+                        continue;
+                    }
+
+                    // goal isn't met - let's see if there's a cached path that seems valid
+                    let stuck_path = if let Some(mut path_state) = worker_state.path_state.take() {
+                        // first call the function that updates the current position
+                        // (or the stuck count if we didn't move)
+                        path_state.check_if_moved_and_update_pos(position);
+
+                        // check only that the path leads to the same destination as opposed
+                        // to the whole goal, so that changes in the avoid_creeps state
+                        // don't invoke a repath
+                        if !path_state.goal.same_destination(&movement_goal) {
+                            // the goal has changed - mark pathing as needed!
+                            None
+                        } else if path_state.stuck_count <= STUCK_REPATH_THRESHOLD {
+                            // still has the same goal as the cached path, and we're not stuck;
+                            // we're ok to simply move, retaining the path unless it's not
+                            // returned
                             worker_state.path_state = worker_reference.move_with_path(
                                 path_state,
                                 position,
                                 &mut moving_creeps,
                             );
+                            worker_state.movement_goal = Some(movement_goal);
+                            continue;
+                        } else {
+                            // we're stuck - we'll try to get back onto the path nearby when
+                            // it's our turn to path
+                            Some(path_state)
                         }
+                    } else {
+                        // no cached path found, mark as needed
+                        None
+                    };
 
-                        // put the goal back that we took, since the goal isn't yet met
-                        worker_state.movement_goal = Some(movement_goal);
-                    }
+                    // get in line for the pathfinder, with workers that have been waiting a
+                    // while getting bumped up the queue
+                    let priority = worker_state.role.get_path_priority()
+                        + worker_state.path_wait_ticks * PATH_PRIORITY_PER_WAITED_TICK;
+                    path_requests.push(PathRequest {
+                        worker_id: *worker_id,
+                        worker_reference,
+                        position,
+                        movement_goal,
+                        stuck_path,
+                        priority,
+                    });
                 } else {
                     // no goal, mark as idle!
                     idle_creeps.insert(position, worker_reference);
@@ -197,6 +298,83 @@ pub fn run_movement_and_remove_worker_refs(shard_state: &mut ShardState) {
         }
     }
 
+    // work out how much CPU we can spend on finding paths this tick - less when the bucket is
+    // low, and never past the point where the tick's usage is considered high
+    let tick_cpu = game::cpu::get_used();
+    let bucket_cpu = game::cpu::bucket();
+    let path_budget = if bucket_cpu < LOW_BUCKET_THRESHOLD {
+        LOW_BUCKET_PATH_CPU_BUDGET
+    } else {
+        PATH_CPU_BUDGET
+    };
+    let path_deadline = (tick_cpu + path_budget).min(HIGH_CPU_THRESHOLD);
+
+    path_requests.sort_by_key(|request| std::cmp::Reverse(request.priority));
+    let mut unserved_count = 0;
+
+    for request in path_requests {
+        let PathRequest {
+            worker_id,
+            worker_reference,
+            position,
+            movement_goal,
+            stuck_path,
+            ..
+        } = request;
+
+        let worker_state = match shard_state.worker_state.get_mut(&worker_id) {
+            Some(worker_state) => worker_state,
+            None => continue,
+        };
+
+        let path_state = if game::cpu::get_used() < path_deadline {
+            worker_state.path_wait_ticks = 0;
+            // if we were stuck, first try a short detour back onto the path we had
+            let repaired = match stuck_path {
+                Some(mut path_state) => path_state.repair_path(position).then_some(path_state),
+                None => None,
+            };
+            Some(repaired.unwrap_or_else(|| {
+                find_path(
+                    &movement_goal,
+                    position,
+                    &mut shard_state.path_cache,
+                    &shard_state.portals,
+                )
+            }))
+        } else {
+            // out of budget - fall back to joining a path another creep found if there is
+            // one, otherwise just step toward the goal and try again next tick
+            worker_state.path_wait_ticks += 1;
+            unserved_count += 1;
+            if movement_goal.is_cacheable() {
+                shard_state
+                    .path_cache
+                    .find_joinable_path(position, &movement_goal)
+            } else {
+                None
+            }
+        };
+
+        match path_state {
+            Some(path_state) => {
+                worker_state.path_state =
+                    worker_reference.move_with_path(path_state, position, &mut moving_creeps);
+            }
+            None => worker_reference.direct_step(&movement_goal, position, &mut moving_creeps),
+        }
+
+        // put the goal back that we took, since the goal isn't yet met
+        worker_state.movement_goal = Some(movement_goal);
+    }
+
+    if unserved_count > 0 {
+        warn!(
+            "path budget exhausted, {} workers fell back to cheap movement (bucket {})",
+            unserved_count, bucket_cpu
+        );
+    }
+
     // look for idle creeps where we actively have creeps saying they intend to move
     for (dest_pos, moving_direction) in moving_creeps.iter() {
         if let Some(worker_reference) = idle_creeps.get(dest_pos) {
@@ -1,38 +1,63 @@
 use screeps::{
-    constants::StructureType,
+    constants::{
+        Part, ResourceType, StructureType, Terrain, ATTACK_POWER, CREEP_RANGED_ACTION_RANGE,
+        RANGED_ATTACK_POWER, ROOM_SIZE, TOWER_ENERGY_COST, TOWER_FALLOFF, TOWER_FALLOFF_RANGE,
+        TOWER_OPTIMAL_RANGE, TOWER_POWER_ATTACK,
+    },
     enums::StructureObject,
     find,
-    local::{LocalCostMatrix, RoomName},
+    local::{
+        linear_index_to_xy, xy_to_linear_index, LocalCostMatrix, LocalRoomTerrain, RoomName, RoomXY,
+    },
+    objects::Room,
     pathfinder::MultiRoomCostResult,
     prelude::*,
 };
 
-pub fn callback_standard(room_name: RoomName) -> MultiRoomCostResult {
+use crate::{
+    constants::*,
+    movement::{DangerProfile, MovementProfile},
+};
+
+// whether the given player is one we've configured as an ally, whose public ramparts we'll
+// walk across and whose creeps and towers we don't treat as a threat
+pub fn is_ally(username: &str) -> bool {
+    ALLIES.contains(&username)
+}
+
+// which variant of the cost matrix a search wants
+#[derive(Debug, Clone, Copy)]
+pub struct CostMatrixOptions {
+    // terrain costs for the creep's speed; roads get a lower cost than plains when moving
+    // on plains would be slow
+    pub profile: MovementProfile,
+    // treat our own and allied creeps as obstacles to route around
+    pub avoid_creeps: bool,
+    // how much to go out of the way to keep out of reach of hostiles
+    pub danger: DangerProfile,
+}
+
+// build the callback for the pathfinder to get each room's cost matrix
+pub fn cost_matrix_callback(
+    options: CostMatrixOptions,
+) -> impl FnMut(RoomName) -> MultiRoomCostResult {
+    move |room_name| build_cost_matrix(room_name, options)
+}
+
+// raise the cost of a tile without making an impassable tile walkable
+fn raise_cost(matrix: &mut LocalCostMatrix, xy: RoomXY, cost: u8) {
+    if matrix.get(xy) < cost {
+        matrix.set(xy, cost);
+    }
+}
+
+fn build_cost_matrix(room_name: RoomName, options: CostMatrixOptions) -> MultiRoomCostResult {
     let mut new_matrix = LocalCostMatrix::new();
     if let Some(room) = screeps::game::rooms().get(room_name) {
-        for structure in room.find(find::STRUCTURES, None) {
-            let pos = structure.pos();
-            match structure {
-                // ignore roads for creeps not needing 'em
-                StructureObject::StructureRoad(_) => {}
-                // containers walkable
-                StructureObject::StructureContainer(_) => {}
-                StructureObject::StructureWall(_) => {
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-                StructureObject::StructureRampart(rampart) => {
-                    // we could check for and path across public ramparts
-                    // (and need to do so if we want to enhance this bot to be able
-                    // to cross an ally's public ramparts - but for now, simply don't trust 'em
-                    if !rampart.my() {
-                        new_matrix.set(pos.xy(), 0xff);
-                    }
-                }
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-            }
+        add_structure_costs(&room, &mut new_matrix, options);
+        add_creep_costs(&room, &mut new_matrix, options);
+        if let Some(damage_per_cost) = damage_per_cost(options.danger) {
+            add_danger_costs(&room, &mut new_matrix, options.profile, damage_per_cost);
         }
 
         for csite in room.find(find::MY_CONSTRUCTION_SITES, None) {
@@ -50,147 +75,233 @@ pub fn callback_standard(room_name: RoomName) -> MultiRoomCostResult {
     MultiRoomCostResult::CostMatrix(new_matrix.into())
 }
 
-pub fn callback_roads(room_name: RoomName) -> MultiRoomCostResult {
-    let mut new_matrix = LocalCostMatrix::new();
-    if let Some(room) = screeps::game::rooms().get(room_name) {
-        for structure in room.find(find::STRUCTURES, None) {
-            let pos = structure.pos();
-            match structure {
-                StructureObject::StructureRoad(_) => {
-                    if new_matrix.get(pos.xy()) == 0 {
-                        new_matrix.set(pos.xy(), 0x01);
-                    }
-                }
-                // containers walkable
-                StructureObject::StructureContainer(_) => {}
-                StructureObject::StructureWall(_) => {
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-                StructureObject::StructureRampart(rampart) => {
-                    // we could check for and path across public ramparts
-                    // (and need to do so if we want to enhance this bot to be able
-                    // to cross an ally's public ramparts - but for now, simply don't trust 'em
-                    if !rampart.my() {
-                        new_matrix.set(pos.xy(), 0xff);
-                    }
+fn add_structure_costs(room: &Room, new_matrix: &mut LocalCostMatrix, options: CostMatrixOptions) {
+    for structure in room.find(find::STRUCTURES, None) {
+        let pos = structure.pos();
+        match structure {
+            StructureObject::StructureRoad(_) => {
+                // ignore roads for creeps not needing 'em
+                if options.profile == MovementProfile::RoadsOneToTwo
+                    && new_matrix.get(pos.xy()) == 0
+                {
+                    new_matrix.set(pos.xy(), 0x01);
                 }
-                _ => {
-                    // other structures, not walkable
+            }
+            // containers walkable
+            StructureObject::StructureContainer(_) => {}
+            // portals are only walkable when we mean to go through them, so make them as
+            // expensive as possible while still allowing them as a destination
+            StructureObject::StructurePortal(_) => {
+                new_matrix.set(pos.xy(), 0xfe);
+            }
+            StructureObject::StructureWall(_) => {
+                new_matrix.set(pos.xy(), 0xff);
+            }
+            StructureObject::StructureRampart(rampart) => {
+                // our own ramparts are walkable, and so are allies' when they've made them
+                // public - anyone else's we simply don't trust
+                let walkable = rampart.my()
+                    || (rampart.is_public()
+                        && rampart
+                            .owner()
+                            .is_some_and(|owner| is_ally(&owner.username())));
+                if !walkable {
                     new_matrix.set(pos.xy(), 0xff);
                 }
             }
+            _ => {
+                // other structures, not walkable
+                new_matrix.set(pos.xy(), 0xff);
+            }
         }
+    }
+}
 
-        for csite in room.find(find::MY_CONSTRUCTION_SITES, None) {
-            let pos = csite.pos();
-            match csite.structure_type() {
-                // walkable structure types
-                StructureType::Container | StructureType::Road | StructureType::Rampart => {}
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
-                }
+fn add_creep_costs(room: &Room, new_matrix: &mut LocalCostMatrix, options: CostMatrixOptions) {
+    for creep in room.find(find::CREEPS, None) {
+        let pos = creep.pos();
+        if creep.my() || is_ally(&creep.owner().username()) {
+            // our creeps and our allies' will likely move out of the way, so only route
+            // around them when asked to
+            if options.avoid_creeps {
+                raise_cost(new_matrix, pos.xy(), 0x20);
             }
+        } else {
+            // hostile creeps won't be swapping places with us
+            new_matrix.set(pos.xy(), 0xff);
         }
     }
-    MultiRoomCostResult::CostMatrix(new_matrix.into())
 }
 
-pub fn callback_standard_avoiding_creeps(room_name: RoomName) -> MultiRoomCostResult {
-    let mut new_matrix = LocalCostMatrix::new();
-    if let Some(room) = screeps::game::rooms().get(room_name) {
-        for structure in room.find(find::STRUCTURES, None) {
-            let pos = structure.pos();
-            match structure {
-                // ignore roads for creeps not needing 'em
-                StructureObject::StructureRoad(_) => {}
-                // containers walkable
-                StructureObject::StructureContainer(_) => {}
-                StructureObject::StructureWall(_) => {
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-                StructureObject::StructureRampart(rampart) => {
-                    // we could check for and path across public ramparts
-                    // (and need to do so if we want to enhance this bot to be able
-                    // to cross an ally's public ramparts - but for now, simply don't trust 'em
-                    if !rampart.my() {
-                        new_matrix.set(pos.xy(), 0xff);
-                    }
-                }
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
-                }
+// damage a tower deals at the given range, per the falloff formula in the game's constants
+fn tower_damage_at_range(range: u32) -> u32 {
+    let optimal = TOWER_OPTIMAL_RANGE as u32;
+    let falloff = TOWER_FALLOFF_RANGE as u32;
+    let range = range.clamp(optimal, falloff);
+    let reduction = TOWER_POWER_ATTACK as f64 * TOWER_FALLOFF * (range - optimal) as f64
+        / (falloff - optimal) as f64;
+    TOWER_POWER_ATTACK - reduction as u32
+}
+
+// add the damage a tower could deal to every tile in the room
+fn add_tower_damage(danger: &mut [u32], tower_xy: RoomXY) {
+    for (i, tile_danger) in danger.iter_mut().enumerate() {
+        let xy = linear_index_to_xy(i);
+        let range = tower_xy
+            .x
+            .u8()
+            .abs_diff(xy.x.u8())
+            .max(tower_xy.y.u8().abs_diff(xy.y.u8()));
+        *tile_danger += tower_damage_at_range(range as u32);
+    }
+}
+
+// how much damage per tick a tile needs to be exposed to for each point of added cost, or none
+// if the search shouldn't look at danger at all
+fn damage_per_cost(danger: DangerProfile) -> Option<u32> {
+    match danger {
+        DangerProfile::Ignore => None,
+        DangerProfile::Cautious => Some(DANGER_CAUTIOUS_DAMAGE_PER_COST),
+        DangerProfile::Avoid => Some(DANGER_AVOID_DAMAGE_PER_COST),
+    }
+}
+
+// add the damage a hostile could deal at a tile to every tile within range of its position
+fn add_damage_in_range(danger: &mut [u32], center: RoomXY, range: u8, damage: u32) {
+    let range = range as i8;
+    for dx in -range..=range {
+        for dy in -range..=range {
+            if let Some(xy) = center.checked_add((dx, dy)) {
+                danger[xy_to_linear_index(xy)] += damage;
             }
         }
+    }
+}
 
-        for creep in room.find(find::CREEPS, None) {
-            let pos = creep.pos();
-            new_matrix.set(pos.xy(), 0x20);
+// raise the cost of tiles where hostile creeps, source keepers, or hostile towers could hit us,
+// weighted by how hard they'd hit
+fn add_danger_costs(
+    room: &Room,
+    new_matrix: &mut LocalCostMatrix,
+    profile: MovementProfile,
+    damage_per_cost: u32,
+) {
+    let mut danger = [0u32; ROOM_SIZE as usize * ROOM_SIZE as usize];
+    let mut any_danger = false;
+
+    for creep in room.find(find::HOSTILE_CREEPS, None) {
+        if is_ally(&creep.owner().username()) {
+            continue;
+        }
+        let xy = creep.pos().xy();
+        // allow for the hostile taking a step towards us
+        let attack = creep.get_active_bodyparts(Part::Attack) as u32 * ATTACK_POWER;
+        if attack > 0 {
+            add_damage_in_range(&mut danger, xy, 2, attack);
+            any_danger = true;
         }
+        let ranged = creep.get_active_bodyparts(Part::RangedAttack) as u32 * RANGED_ATTACK_POWER;
+        if ranged > 0 {
+            add_damage_in_range(&mut danger, xy, CREEP_RANGED_ACTION_RANGE + 1, ranged);
+            any_danger = true;
+        }
+    }
 
-        for csite in room.find(find::MY_CONSTRUCTION_SITES, None) {
-            let pos = csite.pos();
-            match csite.structure_type() {
-                // walkable structure types
-                StructureType::Container | StructureType::Road | StructureType::Rampart => {}
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
+    for structure in room.find(find::HOSTILE_STRUCTURES, None) {
+        match structure {
+            // the keeper spawned here wanders a few tiles out to guard its source
+            StructureObject::StructureKeeperLair(lair) => {
+                let xy = lair.pos().xy();
+                add_damage_in_range(&mut danger, xy, KEEPER_LAIR_OUTER_RANGE, KEEPER_DAMAGE / 2);
+                add_damage_in_range(&mut danger, xy, KEEPER_LAIR_INNER_RANGE, KEEPER_DAMAGE / 2);
+                any_danger = true;
+            }
+            StructureObject::StructureTower(tower) => {
+                // towers that can't fire aren't a threat
+                let hostile = tower
+                    .owner()
+                    .is_some_and(|owner| !is_ally(&owner.username()));
+                if hostile
+                    && tower.store().get_used_capacity(Some(ResourceType::Energy))
+                        >= TOWER_ENERGY_COST
+                {
+                    add_tower_damage(&mut danger, tower.pos().xy());
+                    any_danger = true;
                 }
             }
+            _ => {}
         }
     }
-    MultiRoomCostResult::CostMatrix(new_matrix.into())
+
+    if !any_danger {
+        return;
+    }
+
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    apply_danger_costs(new_matrix, &terrain, &danger, profile, damage_per_cost);
 }
 
-pub fn callback_roads_avoiding_creeps(room_name: RoomName) -> MultiRoomCostResult {
-    let mut new_matrix = LocalCostMatrix::new();
-    if let Some(room) = screeps::game::rooms().get(room_name) {
-        for structure in room.find(find::STRUCTURES, None) {
-            let pos = structure.pos();
-            match structure {
-                StructureObject::StructureRoad(_) => {
-                    if new_matrix.get(pos.xy()) == 0 {
-                        new_matrix.set(pos.xy(), 0x01);
-                    }
-                }
-                // containers walkable
-                StructureObject::StructureContainer(_) => {}
-                StructureObject::StructureWall(_) => {
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-                StructureObject::StructureRampart(rampart) => {
-                    // we could check for and path across public ramparts
-                    // (and need to do so if we want to enhance this bot to be able
-                    // to cross an ally's public ramparts - but for now, simply don't trust 'em
-                    if !rampart.my() {
-                        new_matrix.set(pos.xy(), 0xff);
-                    }
-                }
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-            }
+// add the cost for each tile's danger on top of what it'd cost to walk it; walls and
+// impassable tiles are left alone, since any cost we set on them would make them walkable
+fn apply_danger_costs(
+    new_matrix: &mut LocalCostMatrix,
+    terrain: &LocalRoomTerrain,
+    danger: &[u32],
+    profile: MovementProfile,
+    damage_per_cost: u32,
+) {
+    let (plain_cost, swamp_cost) = profile.terrain_costs();
+    for (i, &tile_danger) in danger.iter().enumerate() {
+        if tile_danger == 0 {
+            continue;
         }
+        let xy = linear_index_to_xy(i);
+        // a zero in the matrix means the terrain cost applies, which we need to add on to
+        let base_cost = match new_matrix.get(xy) {
+            0 => match terrain.get(xy) {
+                Terrain::Plain => plain_cost,
+                Terrain::Swamp => swamp_cost,
+                Terrain::Wall => continue,
+            },
+            0xff => continue,
+            cost => cost,
+        };
+        let added = (tile_danger / damage_per_cost).min(0xfe) as u8;
+        new_matrix.set(xy, base_cost.saturating_add(added).min(0xfe));
+    }
+}
 
-        for creep in room.find(find::CREEPS, None) {
-            let pos = creep.pos();
-            new_matrix.set(pos.xy(), 0x20);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for csite in room.find(find::MY_CONSTRUCTION_SITES, None) {
-            let pos = csite.pos();
-            match csite.structure_type() {
-                // walkable structure types
-                StructureType::Container | StructureType::Road | StructureType::Rampart => {}
-                _ => {
-                    // other structures, not walkable
-                    new_matrix.set(pos.xy(), 0xff);
-                }
-            }
-        }
+    #[test]
+    fn hostile_tower_danger_leaves_walls_alone() {
+        let xy = |x: u8, y: u8| RoomXY::try_from((x, y)).unwrap();
+        // a wall right next to the tower, at (11, 10)
+        let mut bits = Box::new([0u8; ROOM_SIZE as usize * ROOM_SIZE as usize]);
+        bits[10 * ROOM_SIZE as usize + 11] = 1;
+        let terrain = LocalRoomTerrain::new_from_bits(bits);
+        let mut matrix = LocalCostMatrix::new();
+        // a road keeps its cost raised, and a structure stays impassable
+        matrix.set(xy(12, 10), 0x01);
+        matrix.set(xy(10, 12), 0xff);
+
+        let mut danger = [0u32; ROOM_SIZE as usize * ROOM_SIZE as usize];
+        add_tower_damage(&mut danger, xy(10, 10));
+        apply_danger_costs(
+            &mut matrix,
+            &terrain,
+            &danger,
+            MovementProfile::RoadsOneToTwo,
+            DANGER_AVOID_DAMAGE_PER_COST,
+        );
+
+        assert_eq!(matrix.get(xy(11, 10)), 0);
+        assert_eq!(matrix.get(xy(10, 12)), 0xff);
+        assert!(matrix.get(xy(12, 10)) > 0x01);
+        // tiles further from the tower are hit less hard, so cost less
+        assert!(matrix.get(xy(10, 11)) > matrix.get(xy(40, 40)));
+        assert!(matrix.get(xy(40, 40)) > 0);
     }
-    MultiRoomCostResult::CostMatrix(new_matrix.into())
 }
@@ -1,83 +1,158 @@
 use log::*;
 
-use screeps::{constants::Direction, local::Position, pathfinder::SearchOptions};
+use screeps::{
+    local::Position,
+    pathfinder::{SearchGoal, SearchOptions},
+};
 
 use crate::{
     constants::*,
-    movement::{callbacks::*, MovementProfile, PathState},
+    movement::{callbacks::*, DangerProfile, MovementProfile, PathCache, PathState},
 };
 
 // struct for specifying where a creep wants to move and the options the pathfinder
 // will need to know to get them there
-#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct MovementGoal {
     pub pos: Position,
     pub range: u32,
     pub profile: MovementProfile,
     pub avoid_creeps: bool,
+    // when set, the creep wants to get at least `range` away from `pos` (and from each of
+    // the additional goals) instead of within `range` of it
+    pub flee: bool,
+    // further candidate positions along with their own ranges; reaching any one of them meets
+    // the goal, or for flee goals, these are additional positions to stay away from
+    pub additional_goals: Vec<(Position, u32)>,
+    // how far out of the way to go to keep out of reach of hostiles, when the task has a
+    // preference; otherwise it's filled in from the worker's role
+    pub danger: Option<DangerProfile>,
 }
 
 impl MovementGoal {
-    pub fn find_path_to(&self, from_position: Position) -> PathState {
-        let search_result = if self.avoid_creeps {
-            match self.profile {
-                // creep that moves at full speed over swamp, treat swamps as the same as plains
-                MovementProfile::SwampFiveToOne => {
-                    let options = SearchOptions::new(callback_standard_avoiding_creeps)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .swamp_cost(1)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
-                MovementProfile::PlainsOneToOne => {
-                    let options = SearchOptions::new(callback_standard_avoiding_creeps)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
-                // double the cost of swamps and plains to allow roads to be lowest
-                MovementProfile::RoadsOneToTwo => {
-                    let options = SearchOptions::new(callback_roads_avoiding_creeps)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .plain_cost(2)
-                        .swamp_cost(10)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
-            }
+    // get within range of the position, without going out of our way around creeps, and with
+    // the worker's own danger profile
+    pub fn new(pos: Position, range: u32, profile: MovementProfile) -> MovementGoal {
+        MovementGoal {
+            pos,
+            range,
+            profile,
+            avoid_creeps: false,
+            flee: false,
+            additional_goals: Vec::new(),
+            danger: None,
+        }
+    }
+
+    pub fn with_avoid_creeps(mut self, avoid_creeps: bool) -> MovementGoal {
+        self.avoid_creeps = avoid_creeps;
+        self
+    }
+
+    pub fn with_danger(mut self, danger: DangerProfile) -> MovementGoal {
+        self.danger = Some(danger);
+        self
+    }
+
+    // turn this into getting at least the range away from the position, and from each of the
+    // other threats with their own ranges
+    pub fn fleeing(mut self, threats: Vec<(Position, u32)>) -> MovementGoal {
+        self.flee = true;
+        self.additional_goals = threats;
+        self
+    }
+
+    // build a goal that's met by reaching any one of the candidate positions, leaving it to the
+    // pathfinder to pick whichever is cheapest to get to
+    pub fn any_of(
+        mut candidates: Vec<(Position, u32)>,
+        profile: MovementProfile,
+        avoid_creeps: bool,
+    ) -> Option<MovementGoal> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let (pos, range) = candidates.remove(0);
+        let mut goal = MovementGoal::new(pos, range, profile).with_avoid_creeps(avoid_creeps);
+        goal.additional_goals = candidates;
+        Some(goal)
+    }
+
+    // check whether a creep at the given position has nowhere left to move for this goal
+    pub fn is_met(&self, position: Position) -> bool {
+        if self.flee {
+            position.get_range_to(self.pos) >= self.range
+                && self
+                    .additional_goals
+                    .iter()
+                    .all(|(pos, range)| position.get_range_to(*pos) >= *range)
         } else {
-            match self.profile {
-                // creep that moves at full speed over swamp, treat swamps as the same as plains
-                MovementProfile::SwampFiveToOne => {
-                    let options = SearchOptions::new(callback_standard)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .swamp_cost(1)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
-                MovementProfile::PlainsOneToOne => {
-                    let options = SearchOptions::new(callback_standard)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
-                // double the cost of swamps and plains to allow roads to be lowest
-                MovementProfile::RoadsOneToTwo => {
-                    let options = SearchOptions::new(callback_roads)
-                        .max_ops(MAX_OPS)
-                        .max_rooms(MAX_ROOMS)
-                        .plain_cost(2)
-                        .swamp_cost(10)
-                        .heuristic_weight(HEURISTIC_WEIGHT);
-                    screeps::pathfinder::search(from_position, self.pos, self.range, Some(options))
-                }
+            self.reached_goal(position).is_some()
+        }
+    }
+
+    // for goals with several candidates, find out which one a creep at the given position has
+    // reached, so the task can act on that target
+    pub fn reached_goal(&self, position: Position) -> Option<Position> {
+        if self.flee {
+            return None;
+        }
+        std::iter::once(&(self.pos, self.range))
+            .chain(self.additional_goals.iter())
+            .find(|(pos, range)| position.get_range_to(*pos) <= *range)
+            .map(|(pos, _)| *pos)
+    }
+
+    // paths that avoid creeps were only valid for the creep positions at the time of the
+    // search, flee paths depend on where the threats are, and goals with several candidates
+    // don't have a single destination to key on - none of them can be shared
+    pub fn is_cacheable(&self) -> bool {
+        !self.avoid_creeps && !self.flee && self.additional_goals.is_empty()
+    }
+
+    // check whether a cached path for another goal is still headed to the right place;
+    // changes in the avoid_creeps state don't count, so they don't invoke a repath, and
+    // neither do moving threats for flee goals
+    pub fn same_destination(&self, other: &MovementGoal) -> bool {
+        self.pos == other.pos
+            && self.range == other.range
+            && self.flee == other.flee
+            && self.profile == other.profile
+            && self.danger == other.danger
+            && (self.flee || self.additional_goals == other.additional_goals)
+    }
+
+    fn search_goals(&self) -> impl Iterator<Item = SearchGoal> + '_ {
+        std::iter::once(SearchGoal::new(self.pos, self.range)).chain(
+            self.additional_goals
+                .iter()
+                .map(|(pos, range)| SearchGoal::new(*pos, *range)),
+        )
+    }
+
+    pub fn find_path_to(&self, from_position: Position, path_cache: &mut PathCache) -> PathState {
+        // check if another creep has recently found a path we can join before searching
+        let cacheable = self.is_cacheable();
+        if cacheable {
+            if let Some(path_state) = path_cache.find_joinable_path(from_position, self) {
+                return path_state;
             }
-        };
+        }
+
+        let (plain_cost, swamp_cost) = self.profile.terrain_costs();
+        let options = SearchOptions::new(cost_matrix_callback(CostMatrixOptions {
+            profile: self.profile,
+            avoid_creeps: self.avoid_creeps,
+            danger: self.danger.unwrap_or_default(),
+        }))
+        .max_ops(MAX_OPS)
+        .max_rooms(MAX_ROOMS)
+        .plain_cost(plain_cost)
+        .swamp_cost(swamp_cost)
+        .flee(self.flee)
+        .heuristic_weight(HEURISTIC_WEIGHT);
+        let search_result =
+            screeps::pathfinder::search_many(from_position, self.search_goals(), Some(options));
 
         // warn if we got an incomplete path, but still use it
         if search_result.incomplete() {
@@ -88,41 +163,15 @@ impl MovementGoal {
                 self.pos
             );
         }
-        // start cursor from the current postion
-        let mut cursor_pos = from_position;
         // load the path from the search result, which is Vec<Position>
         let positions = search_result.path();
-        // make a Vec<Direction> for our stored path, which is more compact
-        let mut steps = Vec::with_capacity(positions.len());
-        for pos in positions {
-            // skip storing this step if it's just a room boundary change
-            // that'll happen automatically thanks to the edge tile's swap-every-tick
-            if pos.room_name() == cursor_pos.room_name() {
-                match pos.get_direction_to(cursor_pos) {
-                    Some(v) => {
-                        // store the inverse of the direction to cursor_pos,
-                        // since it's earlier in the path
-                        let v = -v;
-                        steps.push(v);
-                    }
-                    None => {
-                        warn!("direction failure?");
-                        break;
-                    }
-                }
-            }
-            cursor_pos = pos;
-        }
+        let path_state = PathState::from_positions(self.clone(), from_position, &positions);
 
-        PathState {
-            goal: *self,
-            stuck_count: 0,
-            last_position: from_position,
-            // in the rare case we got a zero-step incomplete path, just
-            // mark top as the direction we're moving; the path will just fail next tick
-            next_direction: *steps.first().unwrap_or(&Direction::Top),
-            path: steps,
-            path_progress: 0,
+        // only complete paths are worth sharing
+        if cacheable && !search_result.incomplete() {
+            path_cache.insert(from_position, self, positions);
         }
+
+        path_state
     }
 }
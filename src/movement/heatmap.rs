@@ -0,0 +1,215 @@
+use log::*;
+use std::collections::HashMap;
+
+use screeps::{
+    constants::ROOM_SIZE,
+    game,
+    local::{linear_index_to_xy, xy_to_linear_index, Position, RoomName, RoomXY},
+    raw_memory,
+    visual::{RectStyle, RoomVisual},
+};
+
+use crate::constants::*;
+
+const ROOM_TILES: usize = ROOM_SIZE as usize * ROOM_SIZE as usize;
+
+// counts of how many times creeps have stepped onto each tile of a room, saturating at the
+// top of the range and decaying over time so that old traffic patterns fade out
+#[derive(Clone)]
+pub struct RoomHeatmap {
+    counts: Box<[u8; ROOM_TILES]>,
+}
+
+impl Default for RoomHeatmap {
+    fn default() -> RoomHeatmap {
+        RoomHeatmap {
+            counts: Box::new([0; ROOM_TILES]),
+        }
+    }
+}
+
+impl RoomHeatmap {
+    pub fn get(&self, xy: RoomXY) -> u8 {
+        self.counts[xy_to_linear_index(xy)]
+    }
+
+    // tiles that have seen at least the given amount of traffic, busiest first
+    pub fn tiles_above(&self, threshold: u8) -> Vec<(RoomXY, u8)> {
+        let mut tiles: Vec<(RoomXY, u8)> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count >= threshold && **count > 0)
+            .map(|(i, count)| (linear_index_to_xy(i), *count))
+            .collect();
+        tiles.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        tiles
+    }
+
+    fn total(&self) -> u32 {
+        self.counts.iter().map(|count| *count as u32).sum()
+    }
+
+    fn to_hex(&self) -> String {
+        let mut hex = String::with_capacity(ROOM_TILES * 2);
+        for count in self.counts.iter() {
+            hex.push_str(&format!("{:02x}", count));
+        }
+        hex
+    }
+
+    fn from_hex(hex: &str) -> Option<RoomHeatmap> {
+        if hex.len() != ROOM_TILES * 2 {
+            return None;
+        }
+        let mut heatmap = RoomHeatmap::default();
+        for (i, count) in heatmap.counts.iter_mut().enumerate() {
+            *count = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+        Some(heatmap)
+    }
+}
+
+// record of where creeps actually walk, as input for deciding where roads are worth having
+#[derive(Default)]
+pub struct Heatmap {
+    rooms: HashMap<RoomName, RoomHeatmap>,
+    // whether we've read back the copy saved to the memory segment since the global reset
+    loaded_from_segment: bool,
+    // when the traffic we have goes back to: the first step recorded since the global reset,
+    // or long ago if we've got saved traffic from before it
+    recording_since: Option<u32>,
+}
+
+impl Heatmap {
+    // note that a creep stepped onto a tile
+    pub fn record(&mut self, pos: Position) {
+        if self.recording_since.is_none() {
+            self.recording_since = Some(game::time());
+        }
+        let room_heatmap = self.rooms.entry(pos.room_name()).or_default();
+        let count = &mut room_heatmap.counts[xy_to_linear_index(pos.xy())];
+        *count = count.saturating_add(1);
+    }
+
+    pub fn get(&self, pos: Position) -> u8 {
+        self.rooms
+            .get(&pos.room_name())
+            .map_or(0, |room_heatmap| room_heatmap.get(pos.xy()))
+    }
+
+    pub fn room(&self, room_name: RoomName) -> Option<&RoomHeatmap> {
+        self.rooms.get(&room_name)
+    }
+
+    // whether there's been long enough to see all the regular traffic, so that a tile that's
+    // still cold really isn't being walked on
+    pub fn is_established(&self, tick: u32) -> bool {
+        self.recording_since
+            .is_some_and(|since| tick.saturating_sub(since) >= HEATMAP_DECAY_INTERVAL)
+    }
+
+    // halve every count, dropping rooms that have gone completely cold
+    pub fn decay(&mut self) {
+        for room_heatmap in self.rooms.values_mut() {
+            for count in room_heatmap.counts.iter_mut() {
+                *count /= 2;
+            }
+        }
+        self.rooms
+            .retain(|_, room_heatmap| room_heatmap.counts.iter().any(|count| *count > 0));
+    }
+
+    // once after a global reset, read back the saved copy from the segment (requesting it first
+    // if it's not yet active); after that, save our copy to it periodically
+    pub fn sync_segment(&mut self, segment: u8, tick: u32, requested_segments: &mut Vec<u8>) {
+        if !self.loaded_from_segment {
+            match raw_memory::segments().get(segment) {
+                Some(data) => {
+                    self.load(&data);
+                    self.loaded_from_segment = true;
+                }
+                None => requested_segments.push(segment),
+            }
+        } else if tick.is_multiple_of(HEATMAP_SAVE_INTERVAL) {
+            raw_memory::segments().set(segment, self.save());
+        }
+    }
+
+    fn load(&mut self, data: &str) {
+        if data.is_empty() {
+            return;
+        }
+        let saved: HashMap<RoomName, String> = match serde_json::from_str(data) {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("couldn't parse saved heatmap: {:?}", e);
+                return;
+            }
+        };
+        if !saved.is_empty() {
+            self.recording_since = Some(0);
+        }
+        for (room_name, hex) in saved {
+            match RoomHeatmap::from_hex(&hex) {
+                // keep whatever's been recorded since the reset, if it's more
+                Some(saved_heatmap) => {
+                    let room_heatmap = self.rooms.entry(room_name).or_default();
+                    for (count, saved_count) in room_heatmap
+                        .counts
+                        .iter_mut()
+                        .zip(saved_heatmap.counts.iter())
+                    {
+                        *count = (*count).max(*saved_count);
+                    }
+                }
+                None => warn!("bad saved heatmap for {}", room_name),
+            }
+        }
+    }
+
+    fn save(&self) -> String {
+        // the busiest rooms get saved first, in case they don't all fit in the segment
+        let mut rooms: Vec<(&RoomName, &RoomHeatmap)> = self.rooms.iter().collect();
+        rooms.sort_by_key(|(_, room_heatmap)| std::cmp::Reverse(room_heatmap.total()));
+
+        let mut saved = HashMap::new();
+        let mut size = 0;
+        for (room_name, room_heatmap) in rooms {
+            // each room is the hex plus a little json overhead for the key
+            size += ROOM_TILES * 2 + 16;
+            if size > HEATMAP_SEGMENT_MAX_BYTES {
+                break;
+            }
+            saved.insert(*room_name, room_heatmap.to_hex());
+        }
+
+        serde_json::to_string(&saved).unwrap_or_default()
+    }
+
+    // shade each tile of the rooms we can see by how much it's been walked on
+    pub fn draw_visuals(&self) {
+        for room_name in game::rooms().keys() {
+            if let Some(room_heatmap) = self.rooms.get(&room_name) {
+                let visual = RoomVisual::new(Some(room_name));
+                for (i, count) in room_heatmap.counts.iter().enumerate() {
+                    if *count == 0 {
+                        continue;
+                    }
+                    let xy = linear_index_to_xy(i);
+                    visual.rect(
+                        xy.x.u8() as f32 - 0.5,
+                        xy.y.u8() as f32 - 0.5,
+                        1.,
+                        1.,
+                        Some(
+                            RectStyle::default()
+                                .fill("#f80")
+                                .opacity(*count as f32 / u8::MAX as f32 * 0.6),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
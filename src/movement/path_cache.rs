@@ -0,0 +1,284 @@
+use log::*;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use screeps::{
+    constants::{find, StructureType},
+    game,
+    local::{Position, RoomName, RoomXY},
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    movement::{DangerProfile, MovementGoal, MovementProfile, PathState},
+};
+
+// paths are shared between creeps starting from the same coarse area of a room, so that
+// creeps leaving a spawn or a source from slightly different tiles can still reuse them
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct PathCacheKey {
+    pub origin_room: RoomName,
+    pub origin_area: (u8, u8),
+    pub destination: Position,
+    pub range: u32,
+    pub profile: MovementProfile,
+    // paths searched with danger weighting steer around threats, so aren't shared with
+    // searches that don't care about them, or care differently
+    pub danger: DangerProfile,
+}
+
+impl PathCacheKey {
+    pub fn new(origin: Position, goal: &MovementGoal) -> PathCacheKey {
+        PathCacheKey {
+            origin_room: origin.room_name(),
+            origin_area: (
+                origin.x().u8() / PATH_CACHE_AREA_SIZE,
+                origin.y().u8() / PATH_CACHE_AREA_SIZE,
+            ),
+            destination: goal.pos,
+            range: goal.range,
+            profile: goal.profile,
+            danger: goal.danger.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedPath {
+    // position the search started from, which isn't included in the positions
+    origin: Position,
+    // the full path as returned by the pathfinder, including room edge tiles on both
+    // sides of each exit, so that creeps can join at any point along it
+    positions: Vec<Position>,
+    created: u32,
+    last_used: u32,
+}
+
+impl CachedPath {
+    fn heap_size(&self) -> usize {
+        std::mem::size_of::<PathCacheKey>()
+            + std::mem::size_of::<CachedPath>()
+            + self.positions.capacity() * std::mem::size_of::<Position>()
+    }
+
+    fn touches_room(&self, room_name: RoomName) -> bool {
+        self.origin.room_name() == room_name
+            || self
+                .positions
+                .iter()
+                .any(|pos| pos.room_name() == room_name)
+    }
+
+    // find the furthest point along the path that a creep at the given position
+    // can step onto, returning the index of the first position it should move to
+    fn join_index(&self, from_position: Position) -> Option<usize> {
+        if from_position == self.origin {
+            return Some(0);
+        }
+
+        for (i, pos) in self.positions.iter().enumerate().rev() {
+            if pos.room_name() != from_position.room_name() {
+                continue;
+            }
+            if *pos == from_position {
+                // already standing on the path, continue from the step after this one
+                return Some(i + 1);
+            }
+            if pos.get_range_to(from_position) == 1 {
+                return Some(i);
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct PathCache {
+    paths: HashMap<PathCacheKey, CachedPath>,
+    // estimated heap usage of all cached paths, kept under PATH_CACHE_MAX_BYTES
+    heap_bytes: usize,
+    // hash of the structures and construction sites seen in each room at the last check,
+    // used to detect changes that should invalidate paths through the room
+    room_layout_hashes: HashMap<RoomName, u64>,
+}
+
+impl PathCache {
+    pub fn find_joinable_path(
+        &mut self,
+        from_position: Position,
+        goal: &MovementGoal,
+    ) -> Option<PathState> {
+        let key = PathCacheKey::new(from_position, goal);
+        let tick = game::time();
+
+        let cached_path = self.paths.get_mut(&key)?;
+        if tick.saturating_sub(cached_path.created) > PATH_CACHE_TTL {
+            self.remove(&key);
+            return None;
+        }
+
+        let join_index = cached_path.join_index(from_position)?;
+        if join_index >= cached_path.positions.len() {
+            // standing on the end of the path; nothing left to walk
+            return None;
+        }
+
+        cached_path.last_used = tick;
+        Some(PathState::from_positions(
+            goal.clone(),
+            from_position,
+            &cached_path.positions[join_index..],
+        ))
+    }
+
+    pub fn insert(
+        &mut self,
+        from_position: Position,
+        goal: &MovementGoal,
+        positions: Vec<Position>,
+    ) {
+        if positions.is_empty() {
+            return;
+        }
+
+        let tick = game::time();
+        let key = PathCacheKey::new(from_position, goal);
+        let cached_path = CachedPath {
+            origin: from_position,
+            positions,
+            created: tick,
+            last_used: tick,
+        };
+
+        self.heap_bytes += cached_path.heap_size();
+        if let Some(replaced) = self.paths.insert(key, cached_path) {
+            self.heap_bytes -= replaced.heap_size();
+        }
+
+        self.evict_to_budget();
+    }
+
+    // drop the least recently used paths until we're back under our heap budget
+    fn evict_to_budget(&mut self) {
+        while self.heap_bytes > PATH_CACHE_MAX_BYTES {
+            let oldest_key = match self
+                .paths
+                .iter()
+                .min_by_key(|(_, cached_path)| cached_path.last_used)
+            {
+                Some((key, _)) => *key,
+                None => break,
+            };
+            self.remove(&oldest_key);
+        }
+    }
+
+    fn remove(&mut self, key: &PathCacheKey) {
+        if let Some(removed) = self.paths.remove(key) {
+            self.heap_bytes -= removed.heap_size();
+        }
+    }
+
+    pub fn invalidate_room(&mut self, room_name: RoomName) {
+        let remove_keys: Vec<PathCacheKey> = self
+            .paths
+            .iter()
+            .filter(|(_, cached_path)| cached_path.touches_room(room_name))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in remove_keys {
+            self.remove(&key);
+        }
+    }
+
+    // compare the structures and construction sites of all visible rooms to what was there at
+    // the last check, invalidating paths through any room that's changed, and expire any
+    // paths that have passed their TTL
+    pub fn check_for_invalidation(&mut self) {
+        let tick = game::time();
+
+        for room in game::rooms().values() {
+            let room_name = room.name();
+            let structures = room
+                .find(find::STRUCTURES, None)
+                .into_iter()
+                .map(|structure| (structure.pos().xy(), structure.structure_type(), false));
+            let sites = room
+                .find(find::CONSTRUCTION_SITES, None)
+                .into_iter()
+                .map(|site| (site.pos().xy(), site.structure_type(), true));
+            let layout_hash = room_layout_hash(structures.chain(sites));
+
+            match self.room_layout_hashes.insert(room_name, layout_hash) {
+                Some(previous_hash) if previous_hash != layout_hash => {
+                    debug!(
+                        "structures changed in {}, invalidating cached paths",
+                        room_name
+                    );
+                    self.invalidate_room(room_name);
+                }
+                _ => {}
+            }
+        }
+
+        let expired_keys: Vec<PathCacheKey> = self
+            .paths
+            .iter()
+            .filter(|(_, cached_path)| tick.saturating_sub(cached_path.created) > PATH_CACHE_TTL)
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in expired_keys {
+            self.remove(&key);
+        }
+    }
+}
+
+// a hash of what's built or being built on each tile, which doesn't depend on the order they're
+// found in, so that a site finishing or a structure being swapped for another changes it even
+// when the count doesn't
+fn room_layout_hash(tiles: impl Iterator<Item = (RoomXY, StructureType, bool)>) -> u64 {
+    tiles
+        .map(|tile| {
+            let mut hasher = DefaultHasher::new();
+            tile.hash(&mut hasher);
+            hasher.finish()
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_hash_sees_changes_counts_miss() {
+        let xy = |x: u8, y: u8| RoomXY::try_from((x, y)).unwrap();
+        let site = [
+            (xy(10, 10), StructureType::Spawn, false),
+            (xy(12, 10), StructureType::Wall, true),
+        ];
+        let built = [
+            (xy(10, 10), StructureType::Spawn, false),
+            (xy(12, 10), StructureType::Wall, false),
+        ];
+        let replaced = [
+            (xy(10, 10), StructureType::Spawn, false),
+            (xy(12, 10), StructureType::Road, false),
+        ];
+        let hash =
+            |tiles: &[(RoomXY, StructureType, bool)]| room_layout_hash(tiles.iter().copied());
+
+        // the same layout found in another order is unchanged
+        let reversed: Vec<_> = site.iter().rev().copied().collect();
+        assert_eq!(hash(&site), hash(&reversed));
+        // a site finishing, or a structure replaced with another, is a change
+        assert_ne!(hash(&site), hash(&built));
+        assert_ne!(hash(&built), hash(&replaced));
+    }
+}
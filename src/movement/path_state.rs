@@ -1,6 +1,15 @@
-use screeps::{constants::Direction, local::Position};
+use log::*;
 
-use crate::movement::MovementGoal;
+use screeps::{
+    constants::Direction,
+    local::Position,
+    pathfinder::{SearchGoal, SearchOptions},
+};
+
+use crate::{
+    constants::*,
+    movement::{callbacks::*, MovementGoal},
+};
 
 // struct for tracking the current state of a moving creep
 #[derive(Debug, Clone)]
@@ -13,9 +22,69 @@ pub struct PathState {
     pub next_direction: Direction,
     pub path: Vec<Direction>,
     pub path_progress: usize,
+    // steps of the path that move onto a portal, along with where the portal lands us
+    pub portal_exits: Vec<(usize, Position)>,
 }
 
 impl PathState {
+    pub fn from_positions(
+        goal: MovementGoal,
+        from_position: Position,
+        positions: &[Position],
+    ) -> PathState {
+        // start cursor from the current postion
+        let mut cursor_pos = from_position;
+        // make a Vec<Direction> for our stored path, which is more compact
+        let mut steps = Vec::with_capacity(positions.len());
+        for pos in positions {
+            // skip storing this step if it's just a room boundary change
+            // that'll happen automatically thanks to the edge tile's swap-every-tick
+            if pos.room_name() == cursor_pos.room_name() {
+                match pos.get_direction_to(cursor_pos) {
+                    Some(v) => {
+                        // store the inverse of the direction to cursor_pos,
+                        // since it's earlier in the path
+                        let v = -v;
+                        steps.push(v);
+                    }
+                    None => {
+                        warn!("direction failure?");
+                        break;
+                    }
+                }
+            }
+            cursor_pos = *pos;
+        }
+
+        PathState {
+            goal,
+            stuck_count: 0,
+            last_position: from_position,
+            // in the rare case we got a zero-step incomplete path, just
+            // mark top as the direction we're moving; the path will just fail next tick
+            next_direction: *steps.first().unwrap_or(&Direction::Top),
+            path: steps,
+            path_progress: 0,
+            portal_exits: vec![],
+        }
+    }
+
+    // continue this path through a portal at its end, following the given path that
+    // starts from where the portal lands
+    pub fn append_through_portal(&mut self, exit_position: Position, next_leg: PathState) {
+        if let Some(portal_step) = self.path.len().checked_sub(1) {
+            self.portal_exits.push((portal_step, exit_position));
+            self.portal_exits.extend(
+                next_leg
+                    .portal_exits
+                    .into_iter()
+                    .map(|(step, pos)| (step + self.path.len(), pos)),
+            );
+            self.path.extend(next_leg.path);
+        }
+    }
+
+    // returns whether the creep made the step it intended to
     pub fn check_if_moved_and_update_pos(&mut self, current_position: Position) {
         // first we'll check if the creep actually moved as we intended last tick,
         // incrementing the path_progress if so (and incrementing the stuck_count if not)
@@ -29,11 +98,173 @@ impl PathState {
         } else if current_position == self.last_position {
             // didn't move, simply increment the stuck counter
             self.stuck_count += 1;
+        } else if self.portal_exits.iter().any(|(step, exit)| {
+            *step == self.path_progress
+                && exit.room_name() == current_position.room_name()
+                && exit.get_range_to(current_position) <= 1
+        }) {
+            // we stepped onto a portal and came out the other side, as planned
+            self.last_position = current_position;
+            self.path_progress += 1;
+            self.stuck_count = 0;
         } else {
             // we're not in the right spot. If we're in a different position than we were
-            // last tick, something weird is going on (possibly stuck on an exit tile or portal) -
+            // last tick, something weird is going on (possibly stuck on an exit tile) -
             // we want to repath in this case, so send the stuck count way up to trigger repathing
             self.stuck_count = u8::MAX;
         }
     }
+
+    // positions the creep will stand on for the rest of the path within the current room,
+    // paired with the index of the step that takes it there; this stops short of the next
+    // portal, since stepping onto it takes the creep somewhere else entirely
+    fn remaining_positions_in_room(&self) -> Vec<(usize, Position)> {
+        let mut positions = vec![];
+        let mut cursor_pos = self.last_position;
+        for (i, step) in self.path.iter().enumerate().skip(self.path_progress) {
+            if self
+                .portal_exits
+                .iter()
+                .any(|(portal_step, _)| *portal_step == i)
+            {
+                break;
+            }
+            cursor_pos = cursor_pos + *step;
+            if cursor_pos.room_name() != self.last_position.room_name() {
+                break;
+            }
+            positions.push((i, cursor_pos));
+        }
+        positions
+    }
+
+    // attempt to find a short detour from the current position back onto a later step of the
+    // existing path, avoiding creeps; on success the path is spliced in place and true is
+    // returned, otherwise the path is left as it was and the caller should do a full search
+    pub fn repair_path(&mut self, current_position: Position) -> bool {
+        if current_position.room_name() != self.last_position.room_name() {
+            return false;
+        }
+
+        // rejoin a few steps ahead, past whatever is blocking the next step; edge tiles
+        // are skipped since creeps don't stay on them
+        let rejoin_candidates: Vec<(usize, Position)> = self
+            .remaining_positions_in_room()
+            .into_iter()
+            .skip(LOCAL_REPATH_MIN_REJOIN_STEPS)
+            .take(LOCAL_REPATH_MAX_REJOIN_STEPS)
+            .filter(|(_, pos)| {
+                let (x, y) = (pos.x().u8(), pos.y().u8());
+                x > 0 && x < 49 && y > 0 && y < 49 && *pos != current_position
+            })
+            .collect();
+
+        if rejoin_candidates.is_empty() {
+            return false;
+        }
+
+        let goals = rejoin_candidates
+            .iter()
+            .map(|(_, pos)| SearchGoal::new(*pos, 0));
+        let (plain_cost, swamp_cost) = self.goal.profile.terrain_costs();
+        let options = SearchOptions::new(cost_matrix_callback(CostMatrixOptions {
+            profile: self.goal.profile,
+            avoid_creeps: true,
+            danger: self.goal.danger.unwrap_or_default(),
+        }))
+        .max_ops(LOCAL_REPATH_MAX_OPS)
+        .max_rooms(1)
+        .plain_cost(plain_cost)
+        .swamp_cost(swamp_cost)
+        .heuristic_weight(HEURISTIC_WEIGHT);
+        let search_result =
+            screeps::pathfinder::search_many(current_position, goals, Some(options));
+
+        if search_result.incomplete() {
+            return false;
+        }
+
+        let detour = search_result.path();
+        if detour.is_empty() || detour.len() > LOCAL_REPATH_MAX_DETOUR_STEPS {
+            return false;
+        }
+
+        // figure out which step of the existing path the detour rejoined at
+        let rejoin_pos = detour[detour.len() - 1];
+        let rejoin_index = match rejoin_candidates.iter().find(|(_, pos)| *pos == rejoin_pos) {
+            Some((i, _)) => *i,
+            None => return false,
+        };
+
+        let detour_state = PathState::from_positions(self.goal.clone(), current_position, &detour);
+        let detour_steps = detour_state.path.len();
+        let rejoin_step = rejoin_index + 1;
+        let mut path = detour_state.path;
+        path.extend_from_slice(&self.path[rejoin_step..]);
+
+        self.stuck_count = 0;
+        self.last_position = current_position;
+        self.next_direction = *path.first().unwrap_or(&Direction::Top);
+        self.path = path;
+        self.path_progress = 0;
+        // shift any later portal steps to line up with the spliced path
+        self.portal_exits = self
+            .portal_exits
+            .iter()
+            .filter(|(step, _)| *step >= rejoin_step)
+            .map(|(step, pos)| (step - rejoin_step + detour_steps, *pos))
+            .collect();
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::{RoomCoordinate, RoomName};
+
+    use super::*;
+    use crate::movement::MovementProfile;
+
+    fn pos(x: u8, y: u8, room: &str) -> Position {
+        Position::new(
+            RoomCoordinate::try_from(x).unwrap(),
+            RoomCoordinate::try_from(y).unwrap(),
+            RoomName::new(room).unwrap(),
+        )
+    }
+
+    #[test]
+    fn rejoin_positions_stop_at_the_next_portal() {
+        // two steps to a portal at (13, 10), which lands us in another room to walk on from
+        let start = pos(10, 10, "W1N1");
+        let exit = pos(30, 30, "W5N5");
+        let goal = MovementGoal::new(pos(33, 30, "W5N5"), 0, MovementProfile::RoadsOneToTwo);
+        let mut state = PathState::from_positions(
+            goal,
+            start,
+            &[
+                pos(11, 10, "W1N1"),
+                pos(12, 10, "W1N1"),
+                pos(13, 10, "W1N1"),
+            ],
+        );
+        let next_leg = PathState::from_positions(
+            state.goal.clone(),
+            exit,
+            &[
+                pos(31, 30, "W5N5"),
+                pos(32, 30, "W5N5"),
+                pos(33, 30, "W5N5"),
+            ],
+        );
+        state.append_through_portal(exit, next_leg);
+        assert_eq!(state.path.len(), 6);
+
+        // the steps past the portal walk from where it lands, not from the portal itself
+        assert_eq!(
+            state.remaining_positions_in_room(),
+            vec![(0, pos(11, 10, "W1N1")), (1, pos(12, 10, "W1N1"))]
+        );
+    }
 }
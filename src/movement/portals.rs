@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use screeps::{
+    constants::find,
+    enums::StructureObject,
+    game,
+    local::{Position, RoomName},
+    objects::PortalDestination,
+    prelude::*,
+};
+
+use crate::{constants::*, movement::MovementGoal};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum KnownPortalDestination {
+    // portal to another room on this shard, landing at the given position
+    Room(Position),
+    // portal to another shard, landing in the given room
+    Shard(String, RoomName),
+}
+
+#[derive(Debug, Clone)]
+pub struct KnownPortal {
+    pub pos: Position,
+    pub destination: KnownPortalDestination,
+    pub last_seen: u32,
+}
+
+// portals we've had visibility of, so movement can route through them
+#[derive(Default)]
+pub struct PortalRegistry {
+    portals: HashMap<RoomName, Vec<KnownPortal>>,
+}
+
+impl PortalRegistry {
+    // record the portals in every visible room, dropping any we haven't seen in a long while
+    // in case they've decayed
+    pub fn scan_visible_rooms(&mut self) {
+        let tick = game::time();
+
+        for room in game::rooms().values() {
+            let mut room_portals = vec![];
+            for structure in room.find(find::STRUCTURES, None) {
+                if let StructureObject::StructurePortal(portal) = structure {
+                    let destination = match portal.destination() {
+                        PortalDestination::InterRoom(pos) => KnownPortalDestination::Room(pos),
+                        PortalDestination::InterShard(dest) => {
+                            KnownPortalDestination::Shard(dest.shard(), dest.room())
+                        }
+                    };
+                    room_portals.push(KnownPortal {
+                        pos: portal.pos(),
+                        destination,
+                        last_seen: tick,
+                    });
+                }
+            }
+
+            if room_portals.is_empty() {
+                self.portals.remove(&room.name());
+            } else {
+                self.portals.insert(room.name(), room_portals);
+            }
+        }
+
+        for room_portals in self.portals.values_mut() {
+            room_portals
+                .retain(|portal| tick.saturating_sub(portal.last_seen) < PORTAL_FORGET_TICKS);
+        }
+        self.portals
+            .retain(|_, room_portals| !room_portals.is_empty());
+    }
+
+    pub fn get(&self, pos: Position) -> Option<&KnownPortal> {
+        self.portals
+            .get(&pos.room_name())
+            .and_then(|room_portals| room_portals.iter().find(|portal| portal.pos == pos))
+    }
+
+    // find the shard a portal at this position leads to, if it's an inter-shard portal
+    pub fn destination_shard(&self, pos: Position) -> Option<&str> {
+        match self.get(pos) {
+            Some(KnownPortal {
+                destination: KnownPortalDestination::Shard(shard, _),
+                ..
+            }) => Some(shard),
+            _ => None,
+        }
+    }
+
+    // every shard our known portals lead to, which are the shards creeps can arrive from
+    pub fn known_shards(&self) -> Vec<&str> {
+        let mut shards: Vec<&str> = self
+            .portals
+            .values()
+            .flatten()
+            .filter_map(|portal| match &portal.destination {
+                KnownPortalDestination::Shard(shard, _) => Some(shard.as_str()),
+                KnownPortalDestination::Room(_) => None,
+            })
+            .collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+    }
+
+    // the closest known portal, by linear distance, that leads to the given shard
+    pub fn closest_to_shard(&self, from_position: Position, shard: &str) -> Option<Position> {
+        self.portals
+            .values()
+            .flatten()
+            .filter(|portal| {
+                matches!(&portal.destination, KnownPortalDestination::Shard(s, _) if s == shard)
+            })
+            .min_by_key(|portal| from_position.get_range_to(portal.pos))
+            .map(|portal| portal.pos)
+    }
+
+    // check whether going through one of the intra-shard portals we know about is enough of a
+    // shortcut to the goal to be worth it, returning the portal position and where it lands
+    pub fn find_shortcut(
+        &self,
+        from_position: Position,
+        goal: &MovementGoal,
+    ) -> Option<(Position, Position)> {
+        if goal.flee || !goal.additional_goals.is_empty() {
+            return None;
+        }
+
+        let direct_distance = from_position.get_range_to(goal.pos);
+        if direct_distance < PORTAL_MIN_SAVINGS {
+            return None;
+        }
+
+        self.portals
+            .values()
+            .flatten()
+            .filter_map(|portal| match portal.destination {
+                KnownPortalDestination::Room(exit) => {
+                    let distance =
+                        from_position.get_range_to(portal.pos) + exit.get_range_to(goal.pos);
+                    Some((distance, portal.pos, exit))
+                }
+                KnownPortalDestination::Shard(..) => None,
+            })
+            .filter(|(distance, _, _)| distance + PORTAL_MIN_SAVINGS <= direct_distance)
+            .min_by_key(|(distance, _, _)| *distance)
+            .map(|(_, portal_pos, exit)| (portal_pos, exit))
+    }
+}
@@ -0,0 +1,1059 @@
+use std::collections::HashMap;
+
+use screeps::{
+    constants::{find, StructureType, Terrain, ROOM_SIZE},
+    local::{LocalRoomTerrain, RoomName, RoomXY},
+    objects::Room,
+    prelude::*,
+    visual::{CircleStyle, RectStyle, RoomVisual, TextStyle},
+};
+
+use crate::{constants::*, movement::RoomHeatmap};
+
+mod buildings;
+mod grid;
+mod min_cut;
+mod perimeter;
+mod roads;
+mod stamps;
+
+pub use self::{
+    buildings::{blueprint_from_room, parse_buildings, to_buildings, validate_blueprint},
+    roads::{exit_tiles, plan_exit_road},
+};
+use self::{grid::*, perimeter::plan_perimeter, roads::*, stamps::*};
+
+// a structure the planner wants built, and the controller level it can be built at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlannedStructure {
+    pub xy: RoomXY,
+    pub structure_type: StructureType,
+    pub rcl: u8,
+}
+
+// the full layout for a room at every controller level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blueprint {
+    // center of the core stamp
+    pub anchor: RoomXY,
+    pub structures: Vec<PlannedStructure>,
+    // hits to keep each rampart and wall at, once the controller is at level 8
+    pub hit_targets: HashMap<RoomXY, u32>,
+}
+
+impl Blueprint {
+    // everything that can be built once the controller reaches the given level
+    pub fn structures_for_rcl(&self, rcl: u8) -> impl Iterator<Item = &PlannedStructure> {
+        self.structures
+            .iter()
+            .filter(move |structure| structure.rcl <= rcl)
+    }
+
+    // hits to keep the rampart or wall at a tile at, for the given controller level
+    pub fn hits_target(&self, xy: RoomXY, rcl: u8) -> Option<u32> {
+        let full_target = *self.hit_targets.get(&xy)? as u64;
+        let rcl_hits = RAMPART_HITS_BY_RCL[rcl.min(8) as usize] as u64;
+        Some((full_target * rcl_hits / RAMPART_HITS_BY_RCL[8] as u64) as u32)
+    }
+
+    pub fn structures_at(&self, xy: RoomXY) -> impl Iterator<Item = &PlannedStructure> {
+        self.structures
+            .iter()
+            .filter(move |structure| structure.xy == xy)
+    }
+
+    // show where everything would go, marking each structure with its type and the
+    // controller level it's built at
+    pub fn draw_visuals(&self, room_name: RoomName) {
+        let visual = RoomVisual::new(Some(room_name));
+        for structure in self.structures.iter() {
+            let (x, y) = (structure.xy.x.u8() as f32, structure.xy.y.u8() as f32);
+            match structure.structure_type {
+                StructureType::Road => {
+                    visual.circle(x, y, Some(CircleStyle::default().radius(0.15).fill("#888")))
+                }
+                StructureType::Rampart => visual.rect(
+                    x - 0.45,
+                    y - 0.45,
+                    0.9,
+                    0.9,
+                    Some(RectStyle::default().fill("#0f0").opacity(0.15)),
+                ),
+                structure_type => {
+                    visual.rect(
+                        x - 0.4,
+                        y - 0.4,
+                        0.8,
+                        0.8,
+                        Some(RectStyle::default().fill("#48f").opacity(0.3)),
+                    );
+                    visual.text(
+                        x,
+                        y + 0.15,
+                        format!("{}{}", visual_label(structure_type), structure.rcl),
+                        Some(TextStyle::default().font(0.35).color("#fff")),
+                    );
+                }
+            }
+        }
+    }
+}
+
+// short name for a structure type, to fit on its tile in a preview
+fn visual_label(structure_type: StructureType) -> &'static str {
+    match structure_type {
+        StructureType::Spawn => "Sp",
+        StructureType::Extension => "E",
+        StructureType::Tower => "T",
+        StructureType::Storage => "St",
+        StructureType::Terminal => "Te",
+        StructureType::Link => "Li",
+        StructureType::Lab => "La",
+        StructureType::Container => "C",
+        StructureType::Extractor => "X",
+        StructureType::Factory => "F",
+        StructureType::PowerSpawn => "P",
+        StructureType::Nuker => "N",
+        StructureType::Observer => "O",
+        StructureType::Wall => "W",
+        _ => "?",
+    }
+}
+
+// everything about a room the planner needs to know
+pub struct PlannerInput {
+    pub terrain: LocalRoomTerrain,
+    pub controller: RoomXY,
+    pub sources: Vec<RoomXY>,
+    pub mineral: Option<RoomXY>,
+    // a spawn that's already been placed, which the core gets built around
+    pub spawn: Option<RoomXY>,
+    // roads that are already built, which new roads should follow where they can
+    pub roads: Vec<RoomXY>,
+    // where creeps have been walking, for roads to follow
+    pub heat: Option<RoomHeatmap>,
+}
+
+impl PlannerInput {
+    pub fn from_room(room: &Room, heat: Option<&RoomHeatmap>) -> Option<PlannerInput> {
+        let controller = room.controller()?.pos().xy();
+        let mut sources: Vec<RoomXY> = room
+            .find(find::SOURCES, None)
+            .iter()
+            .map(|source| source.pos().xy())
+            .collect();
+        sources.sort_by_key(|source| (source.y, source.x));
+        let mineral = room
+            .find(find::MINERALS, None)
+            .first()
+            .map(|mineral| mineral.pos().xy());
+        let spawn = room
+            .find(find::MY_SPAWNS, None)
+            .iter()
+            .map(|spawn| spawn.pos().xy())
+            .min_by_key(|spawn| (spawn.y, spawn.x));
+        let roads = room
+            .find(find::STRUCTURES, None)
+            .iter()
+            .filter(|structure| structure.structure_type() == StructureType::Road)
+            .map(|structure| structure.pos().xy())
+            .collect();
+
+        Some(PlannerInput {
+            terrain: LocalRoomTerrain::from(room.get_terrain()),
+            controller,
+            sources,
+            mineral,
+            spawn,
+            roads,
+            heat: heat.cloned(),
+        })
+    }
+}
+
+// working state while laying out a room
+struct Planner<'a> {
+    input: &'a PlannerInput,
+    // tiles creeps can walk on, before anything is built
+    open: Grid<bool>,
+    // open tiles far enough from the room edges to build the base on
+    buildable: Grid<bool>,
+    // tiles near sources, the mineral and the controller that need to be left for the
+    // creeps working them
+    keep_clear: Grid<bool>,
+    // tiles that must stay empty, like the spot in the middle of the core
+    reserved: Grid<bool>,
+    structures: Grid<Option<StructureType>>,
+    roads: Grid<bool>,
+    // roads built for a specific purpose, which wait until that's needed
+    road_rcl: HashMap<RoomXY, u8>,
+    // non-road structures in the order they should be built
+    placed: Vec<(RoomXY, StructureType)>,
+    // structures that aren't needed until a later controller level than they're allowed at
+    structure_rcl: HashMap<RoomXY, u8>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(input: &'a PlannerInput) -> Planner<'a> {
+        let mut obstacles = input.sources.clone();
+        obstacles.push(input.controller);
+        obstacles.extend(input.mineral);
+
+        let mut open = Grid::new(false);
+        let mut buildable = Grid::new(false);
+        for tile in all_tiles() {
+            let walkable = input.terrain.get(tile) != Terrain::Wall && !obstacles.contains(&tile);
+            open.set(tile, walkable);
+            let (x, y) = (tile.x.u8(), tile.y.u8());
+            let inside = x >= PLANNER_EDGE_MARGIN
+                && y >= PLANNER_EDGE_MARGIN
+                && x < ROOM_SIZE - PLANNER_EDGE_MARGIN
+                && y < ROOM_SIZE - PLANNER_EDGE_MARGIN;
+            buildable.set(tile, walkable && inside);
+        }
+
+        let mut keep_clear = Grid::new(false);
+        for tile in all_tiles() {
+            let near_resource = input
+                .sources
+                .iter()
+                .chain(input.mineral.iter())
+                .any(|resource| range(tile, *resource) <= PLANNER_SOURCE_CLEARANCE);
+            let near_controller = range(tile, input.controller) <= PLANNER_CONTROLLER_CLEARANCE;
+            keep_clear.set(tile, near_resource || near_controller);
+        }
+
+        Planner {
+            input,
+            open,
+            buildable,
+            keep_clear,
+            reserved: Grid::new(false),
+            structures: Grid::new(None),
+            roads: Grid::new(false),
+            road_rcl: HashMap::new(),
+            placed: vec![],
+            structure_rcl: HashMap::new(),
+        }
+    }
+
+    fn is_free(&self, tile: RoomXY) -> bool {
+        self.open.get(tile)
+            && self.structures.get(tile).is_none()
+            && !self.roads.get(tile)
+            && !self.reserved.get(tile)
+    }
+
+    // tiles creeps can still walk through with everything planned so far in place
+    fn passable(&self) -> Grid<bool> {
+        let mut passable = Grid::new(false);
+        for tile in all_tiles() {
+            passable.set(
+                tile,
+                self.open.get(tile)
+                    && self.structures.get(tile).is_none()
+                    && !self.reserved.get(tile),
+            );
+        }
+        passable
+    }
+
+    fn place(&mut self, tile: RoomXY, structure_type: StructureType) {
+        self.structures.set(tile, Some(structure_type));
+        self.placed.push((tile, structure_type));
+    }
+
+    fn place_road(&mut self, tile: RoomXY, rcl: Option<u8>) {
+        if self.roads.get(tile) || !self.open.get(tile) || self.structures.get(tile).is_some() {
+            return;
+        }
+        self.roads.set(tile, true);
+        if let Some(rcl) = rcl {
+            self.road_rcl.insert(tile, rcl);
+        }
+    }
+
+    // the core goes around an existing spawn if there is one, otherwise on the open area that's
+    // the shortest walk from the controller and sources
+    fn find_anchor(&self) -> Option<RoomXY> {
+        if let Some(spawn) = self.input.spawn {
+            let (dx, dy) = CORE_FIRST_SPAWN;
+            return spawn.checked_add((-dx, -dy));
+        }
+
+        let space = distance_transform(&self.buildable);
+        let controller_distance = flood_fill(&self.open, &[self.input.controller]);
+        let source_distances: Vec<Grid<u16>> = self
+            .input
+            .sources
+            .iter()
+            .map(|source| flood_fill(&self.open, &[*source]))
+            .collect();
+
+        all_tiles()
+            .filter(|tile| space.get(*tile) > CORE_RADIUS)
+            .filter(|tile| {
+                controller_distance.get(*tile) != u16::MAX
+                    && source_distances.iter().all(|d| d.get(*tile) != u16::MAX)
+            })
+            .map(|tile| {
+                let mut score = controller_distance.get(tile) as u32 * 2;
+                for distance in source_distances.iter() {
+                    score += distance.get(tile) as u32;
+                }
+                (score, tile)
+            })
+            .min_by_key(|(score, tile)| (*score, tile.y, tile.x))
+            .map(|(_, tile)| tile)
+    }
+
+    // lay out the core, returning any of its structures that didn't fit so they can go
+    // elsewhere
+    fn place_core(&mut self, anchor: RoomXY) -> Vec<StructureType> {
+        let mut displaced = vec![];
+
+        self.reserved.set(anchor, true);
+        for (offset, structure_type) in CORE_STAMP {
+            match anchor.checked_add(offset) {
+                Some(tile) if self.buildable.get(tile) && self.is_free(tile) => {
+                    self.place(tile, structure_type)
+                }
+                _ => displaced.push(structure_type),
+            }
+        }
+
+        let radius = CORE_RADIUS as i8;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx.abs().max(dy.abs()) == radius {
+                    if let Some(tile) = anchor.checked_add((dx, dy)) {
+                        self.place_road(tile, None);
+                    }
+                }
+            }
+        }
+
+        displaced
+    }
+
+    // open tiles in order of walking distance from the core, closest first
+    fn tiles_by_distance(&self, anchor: RoomXY) -> Vec<RoomXY> {
+        let distance = flood_fill(&self.open, &[anchor]);
+        let mut tiles: Vec<RoomXY> = all_tiles()
+            .filter(|tile| distance.get(*tile) != u16::MAX)
+            .collect();
+        tiles.sort_by_key(|tile| (distance.get(*tile), tile.y, tile.x));
+        tiles
+    }
+
+    // fill out from the core on a checkerboard, so that every structure has road next to it
+    fn place_checkerboard(&mut self, anchor: RoomXY, mut queue: Vec<StructureType>) {
+        queue.reverse();
+        let anchor_parity = (anchor.x.u8() + anchor.y.u8()) % 2;
+
+        for tile in self.tiles_by_distance(anchor) {
+            let structure_type = match queue.last() {
+                Some(structure_type) => *structure_type,
+                None => return,
+            };
+
+            if (tile.x.u8() + tile.y.u8()) % 2 == anchor_parity
+                || !self.buildable.get(tile)
+                || self.keep_clear.get(tile)
+                || !self.is_free(tile)
+            {
+                continue;
+            }
+
+            let road_tiles: Vec<RoomXY> = ORTHOGONAL
+                .iter()
+                .filter_map(|offset| tile.checked_add(*offset))
+                .filter(|n| self.roads.get(*n) || self.is_free(*n))
+                .collect();
+            if road_tiles.is_empty() {
+                continue;
+            }
+
+            self.place(tile, structure_type);
+            for road_tile in road_tiles {
+                self.place_road(road_tile, None);
+            }
+            queue.pop();
+        }
+    }
+
+    // find the closest spot the lab stamp fits, returning the end of its road to connect up
+    fn place_labs(&mut self, anchor: RoomXY) -> Option<RoomXY> {
+        for center in self.tiles_by_distance(anchor) {
+            for transposed in [false, true] {
+                let orient = |offset: (i8, i8)| {
+                    if transposed {
+                        transpose(offset)
+                    } else {
+                        offset
+                    }
+                };
+
+                let lab_tiles: Option<Vec<RoomXY>> = LAB_STAMP
+                    .iter()
+                    .map(|offset| center.checked_add(orient(*offset)))
+                    .collect();
+                let road_tiles: Option<Vec<RoomXY>> = LAB_STAMP_ROADS
+                    .iter()
+                    .map(|offset| center.checked_add(orient(*offset)))
+                    .collect();
+                let (lab_tiles, road_tiles) = match (lab_tiles, road_tiles) {
+                    (Some(lab_tiles), Some(road_tiles)) => (lab_tiles, road_tiles),
+                    _ => continue,
+                };
+
+                let fits = lab_tiles.iter().all(|tile| {
+                    self.buildable.get(*tile) && !self.keep_clear.get(*tile) && self.is_free(*tile)
+                }) && road_tiles.iter().all(|tile| {
+                    self.buildable.get(*tile) && (self.roads.get(*tile) || self.is_free(*tile))
+                });
+
+                if fits {
+                    for tile in lab_tiles {
+                        self.place(tile, StructureType::Lab);
+                    }
+                    for tile in road_tiles.iter() {
+                        self.place_road(*tile, Some(LAB_RCL));
+                    }
+                    return road_tiles.last().copied();
+                }
+            }
+        }
+        None
+    }
+
+    // connect a tile to the road network by the cheapest route, following existing roads and
+    // busy tiles where that's not too far out of the way
+    fn connect(&mut self, from: RoomXY, rcl: u8) {
+        let mut roads = self.roads.clone();
+        for tile in self.input.roads.iter() {
+            roads.set(*tile, true);
+        }
+        let costs = road_costs(
+            &self.input.terrain,
+            &self.passable(),
+            &roads,
+            self.input.heat.as_ref(),
+        );
+        for tile in route(&costs, &self.roads, &[from]) {
+            self.place_road(tile, Some(rcl));
+        }
+    }
+
+    // of the given candidate tiles, the one closest to the road network by walking distance
+    fn closest_to_network(&self, candidates: impl Iterator<Item = RoomXY>) -> Option<RoomXY> {
+        let network: Vec<RoomXY> = all_tiles().filter(|tile| self.roads.get(*tile)).collect();
+        let distance = flood_fill(&self.passable(), &network);
+        candidates
+            .filter(|tile| distance.get(*tile) != u16::MAX)
+            .min_by_key(|tile| (distance.get(*tile), tile.y, tile.x))
+    }
+
+    // a link by the controller for upgraders to draw from, with a road up to it
+    fn place_controller_link(&mut self) {
+        let controller = self.input.controller;
+        let candidates: Vec<RoomXY> = all_tiles()
+            .filter(|tile| range(*tile, controller) == CONTROLLER_LINK_RANGE)
+            .filter(|tile| self.buildable.get(*tile) && self.is_free(*tile))
+            .collect();
+        if let Some(link_tile) = self.closest_to_network(candidates.into_iter()) {
+            self.place(link_tile, StructureType::Link);
+            let approach = self.closest_to_network(neighbours(link_tile).filter(|n| {
+                self.is_free(*n) && range(*n, controller) <= CONTROLLER_LINK_RANGE + 1
+            }));
+            if let Some(approach) = approach {
+                self.connect(approach, CONTROLLER_ROAD_RCL);
+            }
+        }
+    }
+
+    // a container for a harvester to stand on next to the resource, with a road out to it;
+    // returns the container's tile
+    fn place_mining_container(&mut self, resource: RoomXY, rcl: u8) -> Option<RoomXY> {
+        let container_tile = self.closest_to_network(
+            neighbours(resource).filter(|n| self.is_free(*n) && !is_room_edge(*n)),
+        )?;
+        self.place(container_tile, StructureType::Container);
+
+        let approach = self.closest_to_network(
+            neighbours(container_tile)
+                .filter(|n| self.roads.get(*n) || (self.is_free(*n) && range(*n, resource) > 1)),
+        );
+        if let Some(approach) = approach {
+            self.connect(approach, rcl);
+        }
+        Some(container_tile)
+    }
+
+    // a link next to a source's container, off the road, for the harvester to fill
+    fn place_source_link(&mut self, container_tile: RoomXY) {
+        let link_tile =
+            neighbours(container_tile).find(|n| self.buildable.get(*n) && self.is_free(*n));
+        if let Some(link_tile) = link_tile {
+            self.place(link_tile, StructureType::Link);
+        }
+    }
+
+    fn into_blueprint(self, anchor: RoomXY) -> Blueprint {
+        let roads = all_tiles()
+            .filter(|tile| self.roads.get(*tile))
+            .map(|tile| (tile, self.road_rcl.get(&tile).copied()));
+        let structures = assign_rcl(&self.placed, &self.structure_rcl, roads);
+        Blueprint {
+            anchor,
+            hit_targets: default_hit_targets(&structures),
+            structures,
+        }
+    }
+}
+
+// the standard hit target for every rampart and wall, for blueprints that don't say otherwise
+fn default_hit_targets(structures: &[PlannedStructure]) -> HashMap<RoomXY, u32> {
+    structures
+        .iter()
+        .filter(|structure| {
+            structure.structure_type == StructureType::Rampart
+                || structure.structure_type == StructureType::Wall
+        })
+        .map(|structure| (structure.xy, RAMPART_HITS_BY_RCL[8]))
+        .collect()
+}
+
+// give every structure the lowest controller level its place in the build order allows, or
+// its own level if that's later, and roads the lowest level of the structures they serve
+// unless they have a level of their own; structures beyond what's allowed at level 8 are
+// dropped
+fn assign_rcl(
+    placed: &[(RoomXY, StructureType)],
+    structure_rcl: &HashMap<RoomXY, u8>,
+    roads: impl Iterator<Item = (RoomXY, Option<u8>)>,
+) -> Vec<PlannedStructure> {
+    let mut counts: HashMap<StructureType, u32> = HashMap::new();
+    let mut structures = vec![];
+    let mut tile_rcl = Grid::new(None);
+
+    for (tile, structure_type) in placed.iter() {
+        let count = counts.entry(*structure_type).or_insert(0);
+        let rcl = (1..=8)
+            .find(|rcl| structure_type.controller_structures(*rcl) > *count)
+            .map(|rcl| (rcl as u8).max(structure_rcl.get(tile).copied().unwrap_or(1)));
+        *count += 1;
+        if let Some(rcl) = rcl {
+            // ramparts go over the top of other structures and shouldn't hold up their roads
+            if *structure_type != StructureType::Rampart {
+                tile_rcl.set(*tile, Some(rcl));
+            }
+            structures.push(PlannedStructure {
+                xy: *tile,
+                structure_type: *structure_type,
+                rcl,
+            });
+        }
+    }
+
+    for (tile, road_rcl) in roads {
+        let rcl = road_rcl.unwrap_or_else(|| {
+            neighbours(tile)
+                .filter_map(|n| tile_rcl.get(n))
+                .min()
+                .unwrap_or(1)
+        });
+        structures.push(PlannedStructure {
+            xy: tile,
+            structure_type: StructureType::Road,
+            rcl,
+        });
+    }
+
+    structures
+}
+
+// lay out everything a room needs at every controller level; None if there's nowhere to put
+// the core
+pub fn plan_room(input: &PlannerInput) -> Option<Blueprint> {
+    let mut planner = Planner::new(input);
+    let anchor = planner.find_anchor()?;
+
+    let mut queue = planner.place_core(anchor);
+    queue.extend([StructureType::Tower; 6]);
+    queue.extend([StructureType::Extension; 60]);
+    queue.push(StructureType::Nuker);
+    queue.push(StructureType::Observer);
+    planner.place_checkerboard(anchor, queue);
+
+    let lab_road_end = planner.place_labs(anchor);
+
+    // the controller link goes in before the source links, so it's built first
+    planner.place_controller_link();
+
+    let mut sources = input.sources.clone();
+    let source_distance = flood_fill(&planner.open, &[anchor]);
+    sources.sort_by_key(|source| (source_distance.get(*source), source.y, source.x));
+    let mut source_containers = vec![];
+    for source in sources {
+        source_containers.extend(planner.place_mining_container(source, SOURCE_ROAD_RCL));
+    }
+    for container_tile in source_containers {
+        planner.place_source_link(container_tile);
+    }
+
+    if let Some(mineral) = input.mineral {
+        planner.place(mineral, StructureType::Extractor);
+        // no use for the container until there's an extractor to mine with
+        if let Some(container_tile) = planner.place_mining_container(mineral, MINERAL_RCL) {
+            planner.structure_rcl.insert(container_tile, MINERAL_RCL);
+        }
+    }
+
+    if let Some(lab_road_end) = lab_road_end {
+        planner.connect(lab_road_end, LAB_RCL);
+    }
+
+    // wall it all in, now we know where everything is
+    let mut blueprint = planner.into_blueprint(anchor);
+    let (perimeter, hit_targets) = plan_perimeter(input, &blueprint.structures);
+    blueprint.structures.extend(perimeter);
+    blueprint.hit_targets.extend(hit_targets);
+
+    Some(blueprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screeps::constants::ExitDirection;
+
+    fn load_fixture(text: &str) -> PlannerInput {
+        let mut bits = Box::new([0u8; ROOM_TILES]);
+        let mut controller = None;
+        let mut sources = vec![];
+        let mut mineral = None;
+        let mut spawn = None;
+
+        for (y, line) in text.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let tile = xy(x as u8, y as u8);
+                bits[y * ROOM_SIZE as usize + x] = match c {
+                    '#' => 1,
+                    '~' => 2,
+                    _ => 0,
+                };
+                match c {
+                    'C' => controller = Some(tile),
+                    'S' => sources.push(tile),
+                    'M' => mineral = Some(tile),
+                    'P' => spawn = Some(tile),
+                    _ => {}
+                }
+            }
+        }
+
+        PlannerInput {
+            terrain: LocalRoomTerrain::new_from_bits(bits),
+            controller: controller.expect("fixture has a controller"),
+            sources,
+            mineral,
+            spawn,
+            roads: vec![],
+            heat: None,
+        }
+    }
+
+    fn open_room() -> PlannerInput {
+        load_fixture(include_str!("planner/fixtures/open_room.txt"))
+    }
+
+    fn cramped_room() -> PlannerInput {
+        load_fixture(include_str!("planner/fixtures/cramped_room.txt"))
+    }
+
+    fn count(blueprint: &Blueprint, structure_type: StructureType, rcl: u8) -> u32 {
+        blueprint
+            .structures_for_rcl(rcl)
+            .filter(|structure| structure.structure_type == structure_type)
+            .count() as u32
+    }
+
+    // checks that should hold for any blueprint
+    fn assert_valid(input: &PlannerInput, blueprint: &Blueprint) {
+        let mut occupied = HashMap::new();
+        for structure in blueprint.structures.iter() {
+            let tile = structure.xy;
+            if structure.structure_type == StructureType::Extractor {
+                assert_eq!(Some(tile), input.mineral);
+                continue;
+            }
+            assert_ne!(
+                input.terrain.get(tile),
+                Terrain::Wall,
+                "{:?} on a wall",
+                structure
+            );
+            assert!(
+                !input.sources.contains(&tile) && tile != input.controller,
+                "{:?} on top of a source or controller",
+                structure
+            );
+            // ramparts go over the top of anything
+            if structure.structure_type == StructureType::Rampart {
+                continue;
+            }
+            if let Some(other) = occupied.insert(tile, structure.structure_type) {
+                panic!("{:?} and {:?} on the same tile", structure, other);
+            }
+        }
+
+        for rcl in 1..=8 {
+            for structure_type in [
+                StructureType::Spawn,
+                StructureType::Extension,
+                StructureType::Tower,
+                StructureType::Link,
+                StructureType::Lab,
+                StructureType::Container,
+                StructureType::Storage,
+                StructureType::Terminal,
+            ] {
+                assert!(
+                    count(blueprint, structure_type, rcl)
+                        <= structure_type.controller_structures(rcl as u32),
+                    "too many {:?} at rcl {}",
+                    structure_type,
+                    rcl
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn distance_transform_measures_open_space() {
+        let open = Grid::new(true);
+        let distance = distance_transform(&open);
+        assert_eq!(distance.get(xy(0, 0)), 1);
+        assert_eq!(distance.get(xy(2, 5)), 3);
+        assert_eq!(distance.get(xy(24, 24)), 25);
+        assert_eq!(distance.get(xy(49, 30)), 1);
+
+        let mut open = Grid::new(true);
+        open.set(xy(20, 20), false);
+        let distance = distance_transform(&open);
+        assert_eq!(distance.get(xy(20, 20)), 0);
+        assert_eq!(distance.get(xy(21, 19)), 1);
+        assert_eq!(distance.get(xy(23, 20)), 3);
+    }
+
+    #[test]
+    fn flood_fill_counts_steps_around_walls() {
+        let mut passable = Grid::new(true);
+        let distance = flood_fill(&passable, &[xy(10, 10)]);
+        assert_eq!(distance.get(xy(10, 10)), 0);
+        assert_eq!(distance.get(xy(13, 17)), 7);
+
+        // a wall straight across the room, with a gap at the bottom
+        for y in 0..48 {
+            passable.set(xy(12, y), false);
+        }
+        let distance = flood_fill(&passable, &[xy(10, 10)]);
+        assert_eq!(distance.get(xy(12, 5)), u16::MAX);
+        assert_eq!(distance.get(xy(14, 10)), 76);
+    }
+
+    #[test]
+    fn routes_follow_existing_roads() {
+        let input = open_room();
+        let mut network = Grid::new(false);
+        network.set(xy(10, 10), true);
+        let mut roads = network.clone();
+        for x in 10..=30 {
+            roads.set(xy(x, 10), true);
+        }
+        let costs = road_costs(&input.terrain, &Grid::new(true), &roads, None);
+
+        let path = route(&costs, &network, &[xy(30, 13)]);
+        assert_eq!(path[0], xy(30, 13));
+        assert!(path.contains(&xy(20, 10)));
+        assert!(!path.contains(&xy(10, 10)));
+        assert!(route(&costs, &network, &[xy(10, 10)]).is_empty());
+    }
+
+    #[test]
+    fn exit_roads_go_through_the_perimeter() {
+        let input = open_room();
+        let mut blueprint = plan_room(&input).unwrap();
+        let exits = exit_tiles(&input.terrain, ExitDirection::Top);
+        let added = plan_exit_road(&input, &mut blueprint, &exits, REMOTE_ROAD_RCL);
+        assert!(added > 0);
+        assert_valid(&input, &blueprint);
+
+        let new_roads: Vec<RoomXY> = blueprint
+            .structures
+            .iter()
+            .filter(|s| s.structure_type == StructureType::Road && s.rcl == REMOTE_ROAD_RCL)
+            .map(|s| s.xy)
+            .collect();
+        assert!(new_roads.iter().any(|tile| exits.contains(tile)));
+        for tile in new_roads {
+            assert!(blueprint
+                .structures_at(tile)
+                .all(|s| s.structure_type != StructureType::Wall));
+        }
+    }
+
+    #[test]
+    fn plans_are_deterministic() {
+        let input = open_room();
+        assert_eq!(plan_room(&input), plan_room(&input));
+        let input = cramped_room();
+        assert_eq!(plan_room(&input), plan_room(&input));
+    }
+
+    #[test]
+    fn open_room_gets_a_full_base() {
+        let input = open_room();
+        let blueprint = plan_room(&input).expect("open room should have space for a base");
+        assert_valid(&input, &blueprint);
+
+        assert_eq!(count(&blueprint, StructureType::Spawn, 8), 3);
+        assert_eq!(count(&blueprint, StructureType::Extension, 8), 60);
+        assert_eq!(count(&blueprint, StructureType::Tower, 8), 6);
+        assert_eq!(count(&blueprint, StructureType::Lab, 8), 10);
+        assert_eq!(count(&blueprint, StructureType::Storage, 8), 1);
+        assert_eq!(count(&blueprint, StructureType::Terminal, 8), 1);
+        assert_eq!(count(&blueprint, StructureType::Extractor, 8), 1);
+        assert_eq!(count(&blueprint, StructureType::Link, 8), 4);
+
+        // enough to get going at each level
+        assert_eq!(count(&blueprint, StructureType::Spawn, 1), 1);
+        assert_eq!(count(&blueprint, StructureType::Extension, 2), 5);
+        assert_eq!(count(&blueprint, StructureType::Tower, 3), 1);
+        assert_eq!(count(&blueprint, StructureType::Link, 5), 2);
+    }
+
+    #[test]
+    fn sources_get_containers_and_roads() {
+        let input = open_room();
+        let blueprint = plan_room(&input).unwrap();
+
+        for source in input.sources.iter() {
+            let container = blueprint
+                .structures
+                .iter()
+                .find(|s| s.structure_type == StructureType::Container && range(s.xy, *source) == 1)
+                .expect("every source has a container next to it");
+            assert!(
+                neighbours(container.xy).any(|n| blueprint
+                    .structures_at(n)
+                    .any(|s| s.structure_type == StructureType::Road)),
+                "every source container has a road to it"
+            );
+        }
+    }
+
+    #[test]
+    fn mineral_container_waits_for_extractor() {
+        let input = open_room();
+        let blueprint = plan_room(&input).unwrap();
+        let mineral = input.mineral.expect("fixture has a mineral");
+
+        let container = blueprint
+            .structures
+            .iter()
+            .find(|s| s.structure_type == StructureType::Container && range(s.xy, mineral) == 1)
+            .expect("the mineral has a container next to it");
+        assert_eq!(container.rcl, MINERAL_RCL);
+        // while the sources' containers are wanted right away
+        assert_eq!(
+            count(&blueprint, StructureType::Container, 1),
+            input.sources.len() as u32
+        );
+    }
+
+    #[test]
+    fn existing_spawn_is_kept() {
+        let mut input = open_room();
+        let spawn = xy(30, 30);
+        input.spawn = Some(spawn);
+        let blueprint = plan_room(&input).unwrap();
+        assert_valid(&input, &blueprint);
+
+        let first_spawn = blueprint
+            .structures
+            .iter()
+            .find(|s| s.structure_type == StructureType::Spawn && s.rcl == 1)
+            .unwrap();
+        assert_eq!(first_spawn.xy, spawn);
+    }
+
+    #[test]
+    fn cramped_room_plan_is_valid() {
+        let input = cramped_room();
+        let blueprint = plan_room(&input).expect("cramped room still has space for a core");
+        assert_valid(&input, &blueprint);
+        assert_eq!(count(&blueprint, StructureType::Spawn, 8), 3);
+        assert!(count(&blueprint, StructureType::Extension, 8) > 0);
+    }
+
+    #[test]
+    fn blueprints_survive_export_and_import() {
+        let input = open_room();
+        let blueprint = plan_room(&input).unwrap();
+        let exported = to_buildings(&blueprint);
+        let imported = parse_buildings(&exported).unwrap();
+
+        // roads get their levels worked out again from what's around them, but everything
+        // ends up in the same place
+        assert_eq!(imported.anchor, blueprint.anchor);
+        let tiles = |blueprint: &Blueprint| {
+            let mut tiles: Vec<(u8, u8, String)> = blueprint
+                .structures
+                .iter()
+                .map(|s| (s.xy.x.u8(), s.xy.y.u8(), format!("{:?}", s.structure_type)))
+                .collect();
+            tiles.sort();
+            tiles
+        };
+        assert_eq!(tiles(&imported), tiles(&blueprint));
+        assert!(validate_blueprint(&imported, &input.terrain).is_empty());
+        for rcl in 1..=8 {
+            assert_eq!(
+                count(&imported, StructureType::Extension, rcl),
+                count(&blueprint, StructureType::Extension, rcl)
+            );
+        }
+    }
+
+    #[test]
+    fn bad_blueprints_are_rejected() {
+        let input = open_room();
+
+        // too many spawns for any controller level
+        let spawns = r#"{"buildings":{"spawn":{"pos":[{"x":20,"y":20},{"x":22,"y":20},{"x":24,"y":20},{"x":26,"y":20}]}}}"#;
+        assert!(parse_buildings(spawns).is_err());
+
+        // an extension on a wall, and a tower overlapping a spawn
+        let blocked = r#"{"buildings":{"extension":{"pos":[{"x":0,"y":0}]},"spawn":{"pos":[{"x":15,"y":5}]},"tower":{"pos":[{"x":15,"y":5}]}}}"#;
+        let blueprint = parse_buildings(blocked).unwrap();
+        assert_eq!(validate_blueprint(&blueprint, &input.terrain).len(), 3);
+    }
+
+    #[test]
+    fn min_cut_closes_a_corridor() {
+        // a walled-off area in the middle of the room, with a three-wide way out to the left
+        let mut open = Grid::new(true);
+        for i in 10..=30 {
+            for (x, y) in [(i, 10), (i, 30), (10, i), (30, i)] {
+                open.set(xy(x, y), false);
+            }
+        }
+        for y in 19..=21 {
+            open.set(xy(10, y), true);
+        }
+
+        let mut protected = Grid::new(false);
+        protected.set(xy(20, 20), true);
+        let mut exits = Grid::new(false);
+        for tile in all_tiles().filter(|tile| is_room_edge(*tile)) {
+            exits.set(tile, true);
+        }
+
+        let cut = min_cut::min_cut(&open, &protected, &exits).unwrap();
+        assert_eq!(cut.len(), 3);
+
+        let mut blocked = open.clone();
+        for tile in cut.iter() {
+            blocked.set(*tile, false);
+        }
+        let distance = flood_fill(&blocked, &[xy(20, 20)]);
+        assert!(all_tiles()
+            .filter(|tile| exits.get(*tile))
+            .all(|tile| distance.get(tile) == u16::MAX));
+    }
+
+    #[test]
+    fn perimeter_seals_the_base() {
+        for input in [open_room(), cramped_room()] {
+            let blueprint = plan_room(&input).unwrap();
+            assert_valid(&input, &blueprint);
+
+            let mut passable = Grid::new(false);
+            for tile in all_tiles() {
+                passable.set(tile, input.terrain.get(tile) != Terrain::Wall);
+            }
+            let mut perimeter = 0;
+            for structure in blueprint.structures.iter() {
+                if matches!(
+                    structure.structure_type,
+                    StructureType::Rampart | StructureType::Wall
+                ) {
+                    passable.set(structure.xy, false);
+                    perimeter += 1;
+                    assert!(blueprint.hits_target(structure.xy, 8).is_some());
+                    assert_eq!(blueprint.hits_target(structure.xy, 1), Some(0));
+                }
+            }
+            assert!(perimeter > 0);
+
+            // nothing coming in from an exit can get to the spawns or the controller
+            let exits: Vec<RoomXY> = all_tiles()
+                .filter(|tile| is_room_edge(*tile) && passable.get(*tile))
+                .collect();
+            let distance = flood_fill(&passable, &exits);
+            for structure in blueprint.structures.iter() {
+                if structure.structure_type == StructureType::Spawn {
+                    assert!(neighbours(structure.xy).all(|n| distance.get(n) == u16::MAX));
+                }
+            }
+            assert!(neighbours(input.controller).all(|n| distance.get(n) == u16::MAX));
+        }
+    }
+
+    #[test]
+    fn perimeter_leaves_a_way_out_to_each_side() {
+        // a lone spawn with no roads, so the cut doesn't cross anything that'd need a rampart
+        let input = open_room();
+        let spawn = PlannedStructure {
+            xy: xy(35, 25),
+            structure_type: StructureType::Spawn,
+            rcl: 1,
+        };
+        let (perimeter, _) = plan_perimeter(&input, &[spawn]);
+        assert!(perimeter
+            .iter()
+            .any(|s| s.structure_type == StructureType::Wall));
+
+        // our creeps can cross ramparts but not walls
+        let mut passable = Grid::new(false);
+        for tile in all_tiles() {
+            passable.set(tile, input.terrain.get(tile) != Terrain::Wall);
+        }
+        for structure in perimeter.iter() {
+            passable.set(
+                structure.xy,
+                structure.structure_type == StructureType::Rampart,
+            );
+        }
+        let distance = flood_fill(&passable, &[spawn.xy]);
+        for side in ["top", "right", "bottom", "left"] {
+            let on_side = |tile: RoomXY| match side {
+                "top" => tile.y.u8() == 0,
+                "right" => tile.x.u8() == ROOM_SIZE - 1,
+                "bottom" => tile.y.u8() == ROOM_SIZE - 1,
+                _ => tile.x.u8() == 0,
+            };
+            let exits: Vec<RoomXY> = all_tiles()
+                .filter(|tile| on_side(*tile) && input.terrain.get(*tile) != Terrain::Wall)
+                .collect();
+            assert!(!exits.is_empty());
+            assert!(
+                exits.iter().any(|tile| distance.get(*tile) != u16::MAX),
+                "no way out to the {}",
+                side
+            );
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use screeps::{
+    constants::{find, StructureType, Terrain},
+    local::{LocalRoomTerrain, RoomXY},
+    objects::Room,
+    prelude::*,
+};
+
+use super::{assign_rcl, default_hit_targets, grid::is_room_edge, Blueprint};
+
+// blueprints are stored in the "buildings" format used by community base planning tools:
+// each structure type with a list of the positions it goes at, in the order they should be
+// built. We add an optional anchor, and keep the tools' optional controller level, which
+// limits how much the blueprint can contain.
+#[derive(Serialize, Deserialize)]
+struct BuildingsData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rcl: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchor: Option<PositionData>,
+    // sorted by type name, so the output is the same every time
+    buildings: BTreeMap<String, BuildingPositions>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BuildingPositions {
+    pos: Vec<PositionData>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct PositionData {
+    x: u8,
+    y: u8,
+}
+
+impl From<RoomXY> for PositionData {
+    fn from(xy: RoomXY) -> PositionData {
+        PositionData {
+            x: xy.x.u8(),
+            y: xy.y.u8(),
+        }
+    }
+}
+
+impl TryFrom<PositionData> for RoomXY {
+    type Error = String;
+
+    fn try_from(pos: PositionData) -> Result<RoomXY, String> {
+        RoomXY::try_from((pos.x, pos.y))
+            .map_err(|_| format!("{},{} is outside the room", pos.x, pos.y))
+    }
+}
+
+// structure types we can't place ourselves, which a blueprint can't ask for
+fn is_buildable(structure_type: StructureType) -> bool {
+    !matches!(
+        structure_type,
+        StructureType::KeeperLair
+            | StructureType::Portal
+            | StructureType::Controller
+            | StructureType::PowerBank
+            | StructureType::InvaderCore
+    )
+}
+
+// read a blueprint from the buildings format, giving its structures controller levels in the
+// order they're listed; fails if it's malformed or asks for more than the controller allows
+pub fn parse_buildings(json: &str) -> Result<Blueprint, String> {
+    let data: BuildingsData =
+        serde_json::from_str(json).map_err(|e| format!("couldn't parse blueprint: {}", e))?;
+    let max_rcl = data.rcl.unwrap_or(8);
+    if !(1..=8).contains(&max_rcl) {
+        return Err(format!("invalid rcl {}", max_rcl));
+    }
+
+    let mut types = vec![];
+    for (type_name, positions) in data.buildings {
+        let structure_type: StructureType =
+            serde_json::from_value(Value::String(type_name.clone()))
+                .map_err(|_| format!("unknown structure type {}", type_name))?;
+        if !is_buildable(structure_type) {
+            return Err(format!("can't build {}", type_name));
+        }
+        let limit = structure_type.controller_structures(max_rcl as u32);
+        if positions.pos.len() as u32 > limit {
+            return Err(format!(
+                "{} {} but only {} allowed at rcl {}",
+                positions.pos.len(),
+                type_name,
+                limit,
+                max_rcl
+            ));
+        }
+        let tiles = positions
+            .pos
+            .into_iter()
+            .map(RoomXY::try_from)
+            .collect::<Result<Vec<RoomXY>, String>>()?;
+        types.push((structure_type, tiles));
+    }
+
+    let mut placed = vec![];
+    let mut roads = vec![];
+    for (structure_type, tiles) in types {
+        for tile in tiles {
+            if structure_type == StructureType::Road {
+                roads.push((tile, None));
+            } else {
+                placed.push((tile, structure_type));
+            }
+        }
+    }
+
+    let anchor = match data.anchor {
+        Some(anchor) => RoomXY::try_from(anchor)?,
+        None => placed
+            .iter()
+            .find(|(_, structure_type)| *structure_type == StructureType::Spawn)
+            .or(placed.first())
+            .map(|(tile, _)| *tile)
+            .ok_or("blueprint has no structures")?,
+    };
+
+    let structures = assign_rcl(&placed, &HashMap::new(), roads.into_iter());
+    Ok(Blueprint {
+        anchor,
+        hit_targets: default_hit_targets(&structures),
+        structures,
+    })
+}
+
+// write a blueprint out in the buildings format, with each type's structures in build order
+pub fn to_buildings(blueprint: &Blueprint) -> String {
+    let mut structures = blueprint.structures.clone();
+    // stable, so structures of the same level stay in build order
+    structures.sort_by_key(|structure| structure.rcl);
+
+    let mut buildings: BTreeMap<String, BuildingPositions> = BTreeMap::new();
+    for structure in structures {
+        let type_name = match serde_json::to_value(structure.structure_type) {
+            Ok(Value::String(type_name)) => type_name,
+            _ => continue,
+        };
+        buildings
+            .entry(type_name)
+            .or_insert_with(|| BuildingPositions { pos: vec![] })
+            .pos
+            .push(structure.xy.into());
+    }
+
+    let data = BuildingsData {
+        rcl: Some(8),
+        anchor: Some(blueprint.anchor.into()),
+        buildings,
+    };
+    serde_json::to_string(&data).unwrap_or_default()
+}
+
+// problems with building the blueprint in a room with the given terrain; empty if it's good
+pub fn validate_blueprint(blueprint: &Blueprint, terrain: &LocalRoomTerrain) -> Vec<String> {
+    let mut problems = vec![];
+    let mut tiles: HashMap<RoomXY, Vec<StructureType>> = HashMap::new();
+
+    for structure in blueprint.structures.iter() {
+        let structure_type = structure.structure_type;
+        let xy = structure.xy;
+
+        // roads can be built through walls, and extractors go on the mineral wherever it is
+        if terrain.get(xy) == Terrain::Wall
+            && structure_type != StructureType::Road
+            && structure_type != StructureType::Extractor
+        {
+            problems.push(format!("{:?} at {} is on a wall", structure_type, xy));
+        }
+        if is_room_edge(xy) && structure_type != StructureType::Road {
+            problems.push(format!(
+                "{:?} at {} is on the room edge",
+                structure_type, xy
+            ));
+        }
+
+        let others = tiles.entry(xy).or_default();
+        let shares = |a: StructureType, b: StructureType| {
+            a == StructureType::Rampart
+                || b == StructureType::Rampart
+                || matches!(
+                    (a, b),
+                    (StructureType::Road, StructureType::Container)
+                        | (StructureType::Container, StructureType::Road)
+                )
+        };
+        if let Some(other) = others.iter().find(|other| !shares(structure_type, **other)) {
+            problems.push(format!(
+                "{:?} at {} overlaps a {:?}",
+                structure_type, xy, other
+            ));
+        }
+        others.push(structure_type);
+    }
+
+    problems
+}
+
+// the structures in a room as they currently are, as a blueprint to export and edit
+pub fn blueprint_from_room(room: &Room) -> Option<Blueprint> {
+    let spawn = room
+        .find(find::MY_SPAWNS, None)
+        .iter()
+        .map(|spawn| spawn.pos().xy())
+        .min_by_key(|spawn| (spawn.y, spawn.x));
+
+    let mut structures: Vec<(RoomXY, StructureType)> = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .map(|structure| (structure.pos().xy(), structure.structure_type()))
+        .filter(|(_, structure_type)| is_buildable(*structure_type))
+        .collect();
+
+    // closest to the spawn first, so that's the order they'd be rebuilt in
+    let anchor = spawn.or_else(|| structures.first().map(|(tile, _)| *tile))?;
+    structures.sort_by_key(|(tile, _)| (super::grid::range(*tile, anchor), tile.y, tile.x));
+
+    let (roads, placed): (Vec<_>, Vec<_>) = structures
+        .into_iter()
+        .partition(|(_, structure_type)| *structure_type == StructureType::Road);
+
+    let structures = assign_rcl(
+        &placed,
+        &HashMap::new(),
+        roads.into_iter().map(|(tile, _)| (tile, None)),
+    );
+    Some(Blueprint {
+        anchor,
+        hit_targets: default_hit_targets(&structures),
+        structures,
+    })
+}
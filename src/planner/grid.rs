@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+use screeps::{
+    constants::ROOM_SIZE,
+    local::{xy_to_linear_index, RoomXY},
+};
+
+pub const ROOM_TILES: usize = ROOM_SIZE as usize * ROOM_SIZE as usize;
+
+// offsets to all 8 tiles around a tile, in a fixed order so results are deterministic
+pub const NEIGHBOURS: [(i8, i8); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+// offsets to the 4 tiles sharing an edge with a tile
+pub const ORTHOGONAL: [(i8, i8); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+// a value for every tile in a room
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grid<T: Copy>(Box<[T; ROOM_TILES]>);
+
+impl<T: Copy> Grid<T> {
+    pub fn new(value: T) -> Grid<T> {
+        Grid(Box::new([value; ROOM_TILES]))
+    }
+
+    pub fn get(&self, xy: RoomXY) -> T {
+        self.0[xy_to_linear_index(xy)]
+    }
+
+    pub fn set(&mut self, xy: RoomXY, value: T) {
+        self.0[xy_to_linear_index(xy)] = value;
+    }
+}
+
+pub fn xy(x: u8, y: u8) -> RoomXY {
+    RoomXY::try_from((x, y)).expect("coordinates within the room")
+}
+
+// every tile in the room, row by row
+pub fn all_tiles() -> impl Iterator<Item = RoomXY> {
+    (0..ROOM_SIZE).flat_map(|y| (0..ROOM_SIZE).map(move |x| xy(x, y)))
+}
+
+pub fn neighbours(center: RoomXY) -> impl Iterator<Item = RoomXY> {
+    NEIGHBOURS
+        .iter()
+        .filter_map(move |offset| center.checked_add(*offset))
+}
+
+// tiles on the room's border, where nothing but roads can be built
+pub fn is_room_edge(tile: RoomXY) -> bool {
+    let (x, y) = (tile.x.u8(), tile.y.u8());
+    x == 0 || y == 0 || x == ROOM_SIZE - 1 || y == ROOM_SIZE - 1
+}
+
+pub fn range(a: RoomXY, b: RoomXY) -> u8 {
+    a.x.u8().abs_diff(b.x.u8()).max(a.y.u8().abs_diff(b.y.u8()))
+}
+
+// for each open tile, the range to the closest tile that isn't open (counting everything outside
+// the room as not open); closed tiles are 0. A tile with a value of n has an open square of
+// radius n - 1 around it.
+pub fn distance_transform(open: &Grid<bool>) -> Grid<u8> {
+    let mut distance = Grid::new(0u8);
+
+    // forward pass, looking at the neighbours we've already visited above and to the left
+    for y in 0..ROOM_SIZE {
+        for x in 0..ROOM_SIZE {
+            let tile = xy(x, y);
+            if !open.get(tile) {
+                continue;
+            }
+            let mut closest = u8::MAX;
+            for offset in [(-1, -1), (0, -1), (1, -1), (-1, 0)] {
+                closest = closest.min(tile.checked_add(offset).map_or(0, |n| distance.get(n)));
+            }
+            distance.set(tile, closest.saturating_add(1));
+        }
+    }
+
+    // backward pass, from the bottom right
+    for y in (0..ROOM_SIZE).rev() {
+        for x in (0..ROOM_SIZE).rev() {
+            let tile = xy(x, y);
+            if !open.get(tile) {
+                continue;
+            }
+            let mut closest = distance.get(tile);
+            for offset in [(1, 1), (0, 1), (-1, 1), (1, 0)] {
+                let through = tile
+                    .checked_add(offset)
+                    .map_or(1, |n| distance.get(n).saturating_add(1));
+                closest = closest.min(through);
+            }
+            distance.set(tile, closest);
+        }
+    }
+
+    distance
+}
+
+// steps from the closest of the starting tiles to every tile reachable through passable tiles;
+// u16::MAX for tiles that can't be reached. Starting tiles needn't be passable themselves.
+pub fn flood_fill(passable: &Grid<bool>, starts: &[RoomXY]) -> Grid<u16> {
+    let mut distance = Grid::new(u16::MAX);
+    let mut queue = VecDeque::new();
+
+    for start in starts {
+        distance.set(*start, 0);
+        queue.push_back(*start);
+    }
+
+    while let Some(tile) = queue.pop_front() {
+        let next = distance.get(tile) + 1;
+        for neighbour in neighbours(tile) {
+            if passable.get(neighbour) && distance.get(neighbour) == u16::MAX {
+                distance.set(neighbour, next);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    distance
+}
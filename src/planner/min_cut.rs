@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+
+use screeps::local::{linear_index_to_xy, xy_to_linear_index, RoomXY};
+
+use super::grid::*;
+
+// more than any cut through the room could ever cost
+const UNCUTTABLE: u32 = ROOM_TILES as u32;
+
+struct Edge {
+    to: usize,
+    capacity: u32,
+}
+
+// a flow network, with every edge stored next to its reverse so that edge `i ^ 1` is the
+// reverse of edge `i`
+struct FlowNetwork {
+    edges: Vec<Edge>,
+    adjacent: Vec<Vec<usize>>,
+}
+
+impl FlowNetwork {
+    fn new(node_count: usize) -> FlowNetwork {
+        FlowNetwork {
+            edges: vec![],
+            adjacent: vec![vec![]; node_count],
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, capacity: u32) {
+        self.adjacent[from].push(self.edges.len());
+        self.edges.push(Edge { to, capacity });
+        self.adjacent[to].push(self.edges.len());
+        self.edges.push(Edge {
+            to: from,
+            capacity: 0,
+        });
+    }
+
+    // steps from the source to each node through edges with capacity left, or None where it
+    // can't be reached
+    fn levels(&self, source: usize) -> Vec<Option<u32>> {
+        let mut levels = vec![None; self.adjacent.len()];
+        let mut queue = VecDeque::new();
+        levels[source] = Some(0);
+        queue.push_back(source);
+        while let Some(node) = queue.pop_front() {
+            let next = levels[node].map(|level| level + 1);
+            for edge_index in self.adjacent[node].iter() {
+                let edge = &self.edges[*edge_index];
+                if edge.capacity > 0 && levels[edge.to].is_none() {
+                    levels[edge.to] = next;
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        levels
+    }
+
+    // push flow along a path of increasing level to the sink, returning how much got through
+    fn augment(
+        &mut self,
+        node: usize,
+        sink: usize,
+        limit: u32,
+        levels: &[Option<u32>],
+        next_edge: &mut [usize],
+    ) -> u32 {
+        if node == sink {
+            return limit;
+        }
+        while next_edge[node] < self.adjacent[node].len() {
+            let edge_index = self.adjacent[node][next_edge[node]];
+            let to = self.edges[edge_index].to;
+            let capacity = self.edges[edge_index].capacity;
+            let downhill = match (levels[node], levels[to]) {
+                (Some(level), Some(to_level)) => to_level == level + 1,
+                _ => false,
+            };
+            if capacity > 0 && downhill {
+                let pushed = self.augment(to, sink, limit.min(capacity), levels, next_edge);
+                if pushed > 0 {
+                    self.edges[edge_index].capacity -= pushed;
+                    self.edges[edge_index ^ 1].capacity += pushed;
+                    return pushed;
+                }
+            }
+            next_edge[node] += 1;
+        }
+        0
+    }
+
+    // fill the network with as much flow as it can carry from source to sink (Dinic's
+    // algorithm), returning the total
+    fn max_flow(&mut self, source: usize, sink: usize) -> u32 {
+        let mut total = 0;
+        loop {
+            let levels = self.levels(source);
+            if levels[sink].is_none() {
+                return total;
+            }
+            let mut next_edge = vec![0; self.adjacent.len()];
+            loop {
+                let pushed = self.augment(source, sink, u32::MAX, &levels, &mut next_edge);
+                if pushed == 0 {
+                    break;
+                }
+                total += pushed;
+            }
+        }
+    }
+}
+
+// the fewest open tiles that, if blocked, leave no way to walk from any of the protected tiles
+// to any of the exit tiles. Only tiles that are open and neither protected nor exits can be
+// part of the cut; if the protected tiles touch the exits there's no cut, and we get None.
+pub fn min_cut(
+    open: &Grid<bool>,
+    protected: &Grid<bool>,
+    exits: &Grid<bool>,
+) -> Option<Vec<RoomXY>> {
+    // each tile is split into an entry and an exit node, joined by an edge that costs 1 to cut
+    // if the tile can be part of the cut, so that cutting it blocks the tile itself
+    let entry_node = |xy: RoomXY| xy_to_linear_index(xy) * 2;
+    let exit_node = |xy: RoomXY| xy_to_linear_index(xy) * 2 + 1;
+    let source = ROOM_TILES * 2;
+    let sink = source + 1;
+
+    let mut network = FlowNetwork::new(ROOM_TILES * 2 + 2);
+    for tile in all_tiles().filter(|tile| open.get(*tile)) {
+        let cuttable = !protected.get(tile) && !exits.get(tile);
+        network.add_edge(
+            entry_node(tile),
+            exit_node(tile),
+            if cuttable { 1 } else { UNCUTTABLE },
+        );
+        for neighbour in neighbours(tile).filter(|n| open.get(*n)) {
+            network.add_edge(exit_node(tile), entry_node(neighbour), UNCUTTABLE);
+        }
+        if protected.get(tile) {
+            network.add_edge(source, entry_node(tile), UNCUTTABLE);
+        }
+        if exits.get(tile) {
+            network.add_edge(exit_node(tile), sink, UNCUTTABLE);
+        }
+    }
+
+    if network.max_flow(source, sink) >= UNCUTTABLE {
+        return None;
+    }
+
+    // the cut is the tiles whose entry the source can still reach but whose exit it can't
+    let levels = network.levels(source);
+    let cut = (0..ROOM_TILES)
+        .filter(|i| levels[i * 2].is_some() && levels[i * 2 + 1].is_none())
+        .map(linear_index_to_xy)
+        .collect();
+    Some(cut)
+}
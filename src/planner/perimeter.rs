@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use screeps::{
+    constants::{StructureType, Terrain, ROOM_SIZE, TOWER_FALLOFF_RANGE},
+    local::RoomXY,
+};
+
+use crate::constants::*;
+
+use super::{grid::*, min_cut::min_cut, PlannedStructure, PlannerInput};
+
+// structures that don't need to be inside the walls: roads go everywhere, and the mining
+// structures are out by the resources unless we've chosen to wall those in too
+fn needs_protection(structure: &PlannedStructure, input: &PlannerInput) -> bool {
+    match structure.structure_type {
+        StructureType::Road
+        | StructureType::Container
+        | StructureType::Extractor
+        | StructureType::Rampart
+        | StructureType::Wall => false,
+        _ => {
+            let by_source = input
+                .sources
+                .iter()
+                .any(|source| range(structure.xy, *source) <= 2);
+            let by_controller = range(structure.xy, input.controller) <= CONTROLLER_LINK_RANGE;
+            !by_source && !by_controller
+        }
+    }
+}
+
+// ramparts and walls on the fewest tiles that keep hostiles from getting within range of the
+// base (and the controller and sources, if we're protecting those) without going through
+// them, along with how many hits each should be kept at by rcl 8. Tiles creeps need to walk
+// through or that have something built on them get ramparts, as does at least one tile
+// leading out to each side of the room we can reach; the rest get walls.
+pub fn plan_perimeter(
+    input: &PlannerInput,
+    structures: &[PlannedStructure],
+) -> (Vec<PlannedStructure>, HashMap<RoomXY, u32>) {
+    let mut obstacles = input.sources.clone();
+    obstacles.push(input.controller);
+    obstacles.extend(input.mineral);
+    let mut open = Grid::new(false);
+    for tile in all_tiles() {
+        open.set(
+            tile,
+            input.terrain.get(tile) != Terrain::Wall && !obstacles.contains(&tile),
+        );
+    }
+
+    // anything next to an exit can't be built on, so is as good as an exit itself
+    let exit_tiles: Vec<RoomXY> = all_tiles()
+        .filter(|tile| is_room_edge(*tile) && open.get(*tile))
+        .collect();
+    let exit_range = flood_fill(&Grid::new(true), &exit_tiles);
+    let mut exits = Grid::new(false);
+    for tile in all_tiles() {
+        exits.set(tile, open.get(tile) && exit_range.get(tile) <= 1);
+    }
+
+    let mut protected = Grid::new(false);
+    let mut protect_around = |center: RoomXY, margin: u8| {
+        for tile in all_tiles().filter(|tile| range(*tile, center) <= margin) {
+            // leave room between the protected area and the exits for the walls to go
+            if open.get(tile) && exit_range.get(tile) > 2 {
+                protected.set(tile, true);
+            }
+        }
+    };
+    for structure in structures.iter().filter(|s| needs_protection(s, input)) {
+        protect_around(structure.xy, RAMPART_MARGIN);
+    }
+    if RAMPARTS_PROTECT_CONTROLLER {
+        protect_around(input.controller, CONTROLLER_LINK_RANGE);
+    }
+    if RAMPARTS_PROTECT_SOURCES {
+        for source in input.sources.iter() {
+            protect_around(*source, 2);
+        }
+    }
+
+    let cut = match min_cut(&open, &protected, &exits) {
+        Some(cut) => cut,
+        None => return (vec![], HashMap::new()),
+    };
+
+    let towers: Vec<RoomXY> = structures
+        .iter()
+        .filter(|s| s.structure_type == StructureType::Tower)
+        .map(|s| s.xy)
+        .collect();
+
+    let mut ramparts: Vec<RoomXY> = cut
+        .iter()
+        .copied()
+        .filter(|tile| structures.iter().any(|s| s.xy == *tile))
+        .collect();
+    ramparts.extend(exit_ramparts(&open, &protected, &cut, &ramparts));
+
+    let mut perimeter = vec![];
+    let mut hit_targets = HashMap::new();
+    for tile in cut {
+        perimeter.push(PlannedStructure {
+            xy: tile,
+            structure_type: if ramparts.contains(&tile) {
+                StructureType::Rampart
+            } else {
+                StructureType::Wall
+            },
+            rcl: RAMPART_RCL,
+        });
+
+        // towers do much less damage out past their falloff range, so the walls there need
+        // more hits to hold out on their own
+        let exposed = towers
+            .iter()
+            .all(|tower| range(*tower, tile) >= TOWER_FALLOFF_RANGE);
+        let hits = RAMPART_HITS_BY_RCL[8];
+        hit_targets.insert(
+            tile,
+            if exposed {
+                hits * RAMPART_EXPOSED_HITS_MULTIPLIER
+            } else {
+                hits
+            },
+        );
+    }
+
+    (perimeter, hit_targets)
+}
+
+// tiles of the cut to put extra ramparts on, so that creeps have a way out of each part of the
+// base to every side of the room with exits it connects to, even where no road crosses the cut.
+// For each side that no rampart leads out to yet, the cut tile on the shortest walk from that
+// part of the base out to the side's exits gets one, with ties going to the top-left-most tile.
+fn exit_ramparts(
+    open: &Grid<bool>,
+    protected: &Grid<bool>,
+    cut: &[RoomXY],
+    ramparts: &[RoomXY],
+) -> Vec<RoomXY> {
+    let mut uncut = open.clone();
+    for tile in cut {
+        uncut.set(*tile, false);
+    }
+    // steps from the closest of the flood's starting tiles to a neighbour of the cut tile
+    let closest_neighbour = |distance: &Grid<u16>, tile: RoomXY| {
+        neighbours(tile)
+            .map(|n| distance.get(n))
+            .min()
+            .unwrap_or(u16::MAX)
+    };
+    let outside_distances: Vec<Grid<u16>> = (0..4)
+        .map(|side| {
+            let side_exits: Vec<RoomXY> = all_tiles()
+                .filter(|tile| uncut.get(*tile) && exit_side(*tile) == Some(side))
+                .collect();
+            flood_fill(&uncut, &side_exits)
+        })
+        .collect();
+
+    let mut added: Vec<RoomXY> = vec![];
+    // the walls may split the protected tiles into separate areas, each needing its own way out
+    let mut seen = Grid::new(false);
+    for start in all_tiles() {
+        if !protected.get(start) || seen.get(start) {
+            continue;
+        }
+        let inside_distance = flood_fill(&uncut, &[start]);
+        for tile in all_tiles() {
+            if inside_distance.get(tile) != u16::MAX {
+                seen.set(tile, true);
+            }
+        }
+
+        for outside_distance in outside_distances.iter() {
+            let connects = |tile: &RoomXY| {
+                closest_neighbour(&inside_distance, *tile) != u16::MAX
+                    && closest_neighbour(outside_distance, *tile) != u16::MAX
+            };
+            if ramparts.iter().chain(added.iter()).any(connects) {
+                continue;
+            }
+
+            let best = cut.iter().copied().filter(connects).min_by_key(|tile| {
+                let steps = closest_neighbour(&inside_distance, *tile) as u32
+                    + closest_neighbour(outside_distance, *tile) as u32;
+                (steps, tile.y, tile.x)
+            });
+            added.extend(best);
+        }
+    }
+    added
+}
+
+// which side of the room an edge tile is on, numbered clockwise from the top; corners are
+// always walls, so each exit tile has just the one
+fn exit_side(tile: RoomXY) -> Option<u8> {
+    let (x, y) = (tile.x.u8(), tile.y.u8());
+    if y == 0 {
+        Some(0)
+    } else if x == ROOM_SIZE - 1 {
+        Some(1)
+    } else if y == ROOM_SIZE - 1 {
+        Some(2)
+    } else if x == 0 {
+        Some(3)
+    } else {
+        None
+    }
+}
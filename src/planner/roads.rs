@@ -0,0 +1,175 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use screeps::{
+    constants::{ExitDirection, StructureType, Terrain, ROOM_SIZE},
+    local::{LocalRoomTerrain, RoomXY},
+};
+
+use crate::{constants::*, movement::RoomHeatmap};
+
+use super::{grid::*, Blueprint, PlannedStructure, PlannerInput};
+
+// the cost of each step for routing roads, in the same proportions creeps on roads move at
+// (doubled, to leave room for the discount on busy tiles); roads that are already there or
+// already planned are cheapest, so routes follow them and merge wherever they can
+const ROAD_COST: u32 = 2;
+const PLAIN_COST: u32 = 4;
+const SWAMP_COST: u32 = 20;
+// taken off tiles that creeps already walk over a lot, so roads go where the traffic is
+const BUSY_TILE_DISCOUNT: u32 = 1;
+// routing through a planned wall means turning it into a rampart, so only go that way if
+// there's no gate anywhere close
+const WALL_CROSSING_COST: u32 = 60;
+
+// the cost of stepping onto each tile when routing roads; None for tiles roads can't go
+// through
+pub fn road_costs(
+    terrain: &LocalRoomTerrain,
+    passable: &Grid<bool>,
+    roads: &Grid<bool>,
+    heat: Option<&RoomHeatmap>,
+) -> Grid<Option<u32>> {
+    let mut costs = Grid::new(None);
+    for tile in all_tiles().filter(|tile| passable.get(*tile)) {
+        let cost = if roads.get(tile) {
+            ROAD_COST
+        } else {
+            let cost = match terrain.get(tile) {
+                Terrain::Plain => PLAIN_COST,
+                Terrain::Swamp => SWAMP_COST,
+                Terrain::Wall => continue,
+            };
+            if heat.is_some_and(|heat| heat.get(tile) >= ROAD_PLANNER_BUSY_HEAT) {
+                cost - BUSY_TILE_DISCOUNT
+            } else {
+                cost
+            }
+        };
+        costs.set(tile, Some(cost));
+    }
+    costs
+}
+
+// the cheapest route from the road network to the closest of the target tiles, as the tiles
+// to put road on from the target back to where it meets the network; empty if the target's
+// already on the network or can't be reached
+pub fn route(costs: &Grid<Option<u32>>, network: &Grid<bool>, targets: &[RoomXY]) -> Vec<RoomXY> {
+    let mut is_target = Grid::new(false);
+    for target in targets {
+        is_target.set(*target, true);
+    }
+
+    let mut cost_so_far = Grid::new(u32::MAX);
+    let mut came_from = Grid::new(None);
+    // position breaks ties, so the same route comes out every time
+    let mut frontier = BinaryHeap::new();
+    for tile in all_tiles().filter(|tile| network.get(*tile)) {
+        cost_so_far.set(tile, 0);
+        frontier.push(Reverse((0, tile.y, tile.x)));
+    }
+
+    while let Some(Reverse((cost, y, x))) = frontier.pop() {
+        let tile = RoomXY { x, y };
+        if cost > cost_so_far.get(tile) {
+            continue;
+        }
+
+        if is_target.get(tile) {
+            let mut path = vec![];
+            let mut step = tile;
+            while !network.get(step) {
+                path.push(step);
+                step = match came_from.get(step) {
+                    Some(previous) => previous,
+                    None => break,
+                };
+            }
+            return path;
+        }
+
+        for neighbour in neighbours(tile) {
+            let step_cost = match costs.get(neighbour) {
+                Some(step_cost) => step_cost,
+                None => continue,
+            };
+            let next_cost = cost + step_cost;
+            if next_cost < cost_so_far.get(neighbour) {
+                cost_so_far.set(neighbour, next_cost);
+                came_from.set(neighbour, Some(tile));
+                frontier.push(Reverse((next_cost, neighbour.y, neighbour.x)));
+            }
+        }
+    }
+
+    vec![]
+}
+
+// the open tiles along the given side of the room, that creeps leave through
+pub fn exit_tiles(terrain: &LocalRoomTerrain, direction: ExitDirection) -> Vec<RoomXY> {
+    let last = ROOM_SIZE - 1;
+    (0..ROOM_SIZE)
+        .map(|i| match direction {
+            ExitDirection::Top => xy(i, 0),
+            ExitDirection::Right => xy(last, i),
+            ExitDirection::Bottom => xy(i, last),
+            ExitDirection::Left => xy(0, i),
+        })
+        .filter(|tile| terrain.get(*tile) != Terrain::Wall)
+        .collect()
+}
+
+// add a road from the blueprint's road network out to the closest of the given exit tiles,
+// for creeps heading out to a remote room; any planned walls in the way become ramparts for
+// the road to pass through. Returns how many tiles of road were added.
+pub fn plan_exit_road(
+    input: &PlannerInput,
+    blueprint: &mut Blueprint,
+    exit_tiles: &[RoomXY],
+    rcl: u8,
+) -> usize {
+    let mut obstacles = input.sources.clone();
+    obstacles.push(input.controller);
+    obstacles.extend(input.mineral);
+
+    let mut passable = Grid::new(false);
+    let mut network = Grid::new(false);
+    let mut walls = Grid::new(false);
+    for tile in all_tiles() {
+        passable.set(
+            tile,
+            input.terrain.get(tile) != Terrain::Wall && !obstacles.contains(&tile),
+        );
+    }
+    for structure in blueprint.structures.iter() {
+        match structure.structure_type {
+            StructureType::Road => network.set(structure.xy, true),
+            StructureType::Wall => walls.set(structure.xy, true),
+            StructureType::Rampart => {}
+            _ => passable.set(structure.xy, false),
+        }
+    }
+
+    let mut roads = network.clone();
+    for tile in input.roads.iter() {
+        roads.set(*tile, true);
+    }
+    let mut costs = road_costs(&input.terrain, &passable, &roads, input.heat.as_ref());
+    for tile in all_tiles().filter(|tile| walls.get(*tile) && passable.get(*tile)) {
+        costs.set(tile, Some(WALL_CROSSING_COST));
+    }
+
+    let path = route(&costs, &network, exit_tiles);
+    for tile in path.iter() {
+        for structure in blueprint.structures.iter_mut() {
+            if structure.xy == *tile && structure.structure_type == StructureType::Wall {
+                structure.structure_type = StructureType::Rampart;
+            }
+        }
+        blueprint.structures.push(PlannedStructure {
+            xy: *tile,
+            structure_type: StructureType::Road,
+            rcl,
+        });
+    }
+    path.len()
+}
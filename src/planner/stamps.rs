@@ -0,0 +1,43 @@
+use screeps::constants::StructureType;
+
+// the center of the base, around the tile where a creep can stand to reach the storage,
+// terminal, core link and spawns at once; listed in the order each type should be built
+pub const CORE_STAMP: [((i8, i8), StructureType); 8] = [
+    ((0, -1), StructureType::Spawn),
+    ((-1, -1), StructureType::Storage),
+    ((-1, 0), StructureType::Link),
+    ((1, -1), StructureType::Terminal),
+    ((1, 0), StructureType::Spawn),
+    ((0, 1), StructureType::Spawn),
+    ((-1, 1), StructureType::Factory),
+    ((1, 1), StructureType::PowerSpawn),
+];
+
+// offset from the core's center to the first spawn, so a room's existing spawn can be
+// built around
+pub const CORE_FIRST_SPAWN: (i8, i8) = (0, -1);
+
+// the core's structures plus the ring of road around them must fit in this radius
+pub const CORE_RADIUS: u8 = 2;
+
+// labs in two columns either side of a road, with the two input labs in the middle row so
+// that every other lab is within range 2 of both; inputs are listed first
+pub const LAB_STAMP: [(i8, i8); 10] = [
+    (-1, 0),
+    (1, 0),
+    (-1, -2),
+    (-1, -1),
+    (-1, 1),
+    (-1, 2),
+    (1, -2),
+    (1, -1),
+    (1, 1),
+    (1, 2),
+];
+
+pub const LAB_STAMP_ROADS: [(i8, i8); 5] = [(0, -2), (0, -1), (0, 0), (0, 1), (0, 2)];
+
+// the same stamp turned on its side, for when it doesn't fit upright
+pub fn transpose(offset: (i8, i8)) -> (i8, i8) {
+    (offset.1, offset.0)
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use screeps::constants::ResourceType;
+
+use crate::{
+    logistics::LogisticsTarget,
+    task::Task,
+    worker::{WorkerId, WorkerReference},
+};
+
+// whether a reservation is on what a target holds, or on its room for more
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+pub enum ReservationKind {
+    Withdraw,
+    Deposit,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reservation {
+    task: Task,
+    target: LogisticsTarget,
+    kind: ReservationKind,
+    amount: u32,
+}
+
+// what a task takes from or brings to, if anything, and which resource
+fn reservation_target(
+    task: &Task,
+) -> Option<(LogisticsTarget, ReservationKind, Option<ResourceType>)> {
+    match task {
+        Task::TakeFromStructure(id, resource_type) => Some((
+            LogisticsTarget::Structure(*id),
+            ReservationKind::Withdraw,
+            Some(*resource_type),
+        )),
+        Task::TakeFromResource(id) => Some((
+            LogisticsTarget::Resource(*id),
+            ReservationKind::Withdraw,
+            None,
+        )),
+        Task::DeliverToStructure(id, resource_type) => Some((
+            LogisticsTarget::Structure(*id),
+            ReservationKind::Deposit,
+            Some(*resource_type),
+        )),
+        Task::DeliverToCreep(id, resource_type) => Some((
+            LogisticsTarget::Creep(*id),
+            ReservationKind::Deposit,
+            Some(*resource_type),
+        )),
+        _ => None,
+    }
+}
+
+// amounts workers are on their way to take from or bring to each target, so that everyone
+// else can look for something that isn't already spoken for
+#[derive(Default)]
+pub struct ReservationLedger {
+    by_worker: HashMap<WorkerId, Reservation>,
+    totals: HashMap<(LogisticsTarget, ReservationKind), u32>,
+}
+
+impl ReservationLedger {
+    // note what a newly assigned task is going to take or bring, going by the worker's store;
+    // a worker only ever holds one reservation, for the task it's been given most recently
+    pub fn reserve(&mut self, worker_id: WorkerId, task: &Task, worker: &WorkerReference) {
+        self.release(worker_id);
+        let (target, kind, resource_type) = match reservation_target(task) {
+            Some(reservation) => reservation,
+            None => return,
+        };
+        let store = worker.store();
+        let amount = match kind {
+            ReservationKind::Withdraw => store.get_free_capacity(resource_type).max(0) as u32,
+            ReservationKind::Deposit => store.get_used_capacity(resource_type),
+        };
+        if amount == 0 {
+            return;
+        }
+
+        *self.totals.entry((target, kind)).or_insert(0) += amount;
+        self.by_worker.insert(
+            worker_id,
+            Reservation {
+                task: *task,
+                target,
+                kind,
+                amount,
+            },
+        );
+    }
+
+    // drop the worker's reservation if it was for the given task, once that's done
+    pub fn release_task(&mut self, worker_id: WorkerId, task: &Task) {
+        if self
+            .by_worker
+            .get(&worker_id)
+            .is_some_and(|reservation| reservation.task == *task)
+        {
+            self.release(worker_id);
+        }
+    }
+
+    // drop whatever the worker has reserved, for when it's finished or gone
+    pub fn release(&mut self, worker_id: WorkerId) {
+        if let Some(reservation) = self.by_worker.remove(&worker_id) {
+            let key = (reservation.target, reservation.kind);
+            if let Some(total) = self.totals.get_mut(&key) {
+                *total = total.saturating_sub(reservation.amount);
+                if *total == 0 {
+                    self.totals.remove(&key);
+                }
+            }
+        }
+    }
+
+    pub fn reserved(&self, target: LogisticsTarget, kind: ReservationKind) -> u32 {
+        self.totals.get(&(target, kind)).copied().unwrap_or(0)
+    }
+
+    // how much of what a target holds is left once everyone on their way has taken their share
+    pub fn unreserved_amount(&self, target: LogisticsTarget, amount: u32) -> u32 {
+        amount.saturating_sub(self.reserved(target, ReservationKind::Withdraw))
+    }
+
+    // how much room a target has left once everyone on their way has filled it
+    pub fn unreserved_capacity(&self, target: LogisticsTarget, capacity: u32) -> u32 {
+        capacity.saturating_sub(self.reserved(target, ReservationKind::Deposit))
+    }
+}
@@ -1,18 +1,18 @@
-use std::collections::HashSet;
-
 use enum_dispatch::enum_dispatch;
 
 use serde::{Deserialize, Serialize};
 
-use screeps::{
-    constants::Part,
-    objects::{Store, StructureSpawn},
-};
+use screeps::constants::Part;
 
-use crate::{task::Task, worker::Worker};
+use crate::{
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 mod builder;
 mod hauler;
+mod lifecycle;
+mod link;
 mod source_harvester;
 mod spawn;
 mod startup;
@@ -20,20 +20,47 @@ mod tower;
 mod upgrader;
 
 pub use self::{
-    builder::Builder, hauler::Hauler, source_harvester::SourceHarvester, spawn::Spawn,
-    startup::Startup, tower::Tower, upgrader::Upgrader,
+    builder::Builder,
+    hauler::Hauler,
+    link::{link_kind, Link, LinkKind},
+    source_harvester::SourceHarvester,
+    spawn::Spawn,
+    startup::Startup,
+    tower::Tower,
+    upgrader::Upgrader,
 };
 
+// creep names are the serialized role, with a generation number after this for replacements
+// spawned while the creep they're replacing is still alive
+const GENERATION_SEPARATOR: char = '#';
+
+pub fn creep_name(role: &WorkerRole, generation: u32) -> String {
+    let name = serde_json::to_string(role).expect("roles should all serialize");
+    if generation == 0 {
+        name
+    } else {
+        format!("{}{}{}", name, GENERATION_SEPARATOR, generation)
+    }
+}
+
+pub fn parse_creep_name(name: &str) -> serde_json::Result<WorkerRole> {
+    let role = match name.rsplit_once(GENERATION_SEPARATOR) {
+        Some((role, _generation)) => role,
+        None => name,
+    };
+    serde_json::from_str(role)
+}
+
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Invalid {}
 
 impl Worker for Invalid {
-    fn find_task(&self, _store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
+    fn find_task(&self, _worker: &WorkerReference, _context: &mut TaskContext) -> Task {
         // broken creep, name didn't parse! doom creep to idle until the end of time
         Task::IdleUntil(u32::MAX)
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
+    fn get_body_for_creep(&self, _energy: u32) -> Vec<Part> {
         panic!("can't spawn invalid workers!")
     }
 }
@@ -58,6 +85,7 @@ pub enum WorkerRole {
     // structures
     Spawn(Spawn),
     Tower(Tower),
+    Link(Link),
 
     // creeps with unparseable names
     Invalid(Invalid),
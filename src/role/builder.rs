@@ -1,17 +1,27 @@
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
-    constants::{find, Part, ResourceType},
+    constants::{find, Part, ResourceType, StructureType},
     enums::StructureObject,
     game,
     local::RoomName,
-    objects::{Room, Store, StructureSpawn},
+    objects::{Creep, Room},
     prelude::*,
 };
 
-use crate::{constants::*, role::WorkerRole, task::Task, worker::Worker};
+use crate::{
+    body::build_body,
+    colony::ColonyState,
+    constants::*,
+    construction::{construction_priority, perimeter_repairs},
+    logistics::LogisticsTarget,
+    movement::DangerProfile,
+    reservation::ReservationLedger,
+    role::{lifecycle::renew_or_recycle, WorkerRole},
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Builder {
@@ -22,13 +32,15 @@ pub struct Builder {
 }
 
 impl Worker for Builder {
-    fn find_task(&self, store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        let store = worker.store();
         match game::rooms().get(self.home_room) {
             Some(room) => {
                 if store.get_used_capacity(Some(ResourceType::Energy)) > 0 {
-                    find_build_or_repair_task(&room, self.repair_watermark)
+                    let colony_state = context.colony_state.get(&self.home_room);
+                    find_build_or_repair_task(&room, self.repair_watermark, colony_state)
                 } else {
-                    find_energy_or_source(&room)
+                    find_energy_or_source(&room, context.reservations)
                 }
             }
             None => {
@@ -38,13 +50,40 @@ impl Worker for Builder {
         }
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
-        use Part::*;
-        vec![Move, Carry, Work]
+    fn find_lifecycle_task(&self, creep: &Creep, context: &mut TaskContext) -> Option<Task> {
+        renew_or_recycle(&WorkerRole::from(*self), creep, self.home_room, context)
+    }
+
+    // builders go wherever there's something to build, so step around invaders and keepers on
+    // the way without going far out of the way for them
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Cautious
+    }
+
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part> {
+        build_body(&BUILDER_BODY, energy, self.get_movement_profile())
     }
 }
 
-fn find_build_or_repair_task(room: &Room, repair_watermark: u32) -> Task {
+fn find_build_or_repair_task(
+    room: &Room,
+    repair_watermark: u32,
+    colony_state: Option<&ColonyState>,
+) -> Task {
+    let blueprint = colony_state.and_then(|colony_state| colony_state.blueprint.as_ref());
+
+    // ramparts and walls the blueprint wants kept up, weakest first
+    let perimeter_repairs =
+        blueprint.map_or(vec![], |blueprint| perimeter_repairs(room, blueprint));
+
+    // anything in the perimeter that's close to falling comes before everything else,
+    // especially fresh ramparts which would otherwise decay away
+    if let Some((structure, target)) = perimeter_repairs.first() {
+        if structure.hits() < RAMPART_URGENT_HITS.min(*target) {
+            return Task::Repair(structure.id(), *target);
+        }
+    }
+
     // look for repair tasks first
     // note that we're using STRUCTURES instead of MY_STRUCTURES
     // so we can catch roads, containers, and walls
@@ -55,34 +94,57 @@ fn find_build_or_repair_task(room: &Room, repair_watermark: u32) -> Task {
         let hits = structure.hits();
         let hits_max = structure.hits_max();
 
+        // the blueprint's ramparts and walls have their own targets
+        if blueprint
+            .is_some_and(|blueprint| blueprint.hit_targets.contains_key(&structure.pos().xy()))
+        {
+            continue;
+        }
+
+        // roads nobody walks on any more are left to decay
+        if structure_object.structure_type() == StructureType::Road
+            && colony_state.is_some_and(|colony_state| {
+                colony_state.unused_roads.contains(&structure.pos().xy())
+            })
+        {
+            continue;
+        }
+
         // if hits_max is 0, it's indestructable
         if hits_max != 0 {
             // if the hits are below our 'watermark' to repair to
             // as well as less than half of this struture's max, repair!
             if hits < repair_watermark && hits * 2 < hits_max {
-                return Task::Repair(structure.id());
+                return Task::Repair(structure.id(), hits_max);
             }
         }
     }
 
-    // look for construction tasks next
+    // look for construction tasks next, building whatever's most important first
     if let Some(construction_site) = room
         .find(find::MY_CONSTRUCTION_SITES, None)
         .into_iter()
-        .next()
+        .min_by_key(|construction_site| construction_priority(construction_site.structure_type()))
     {
         // we can unwrap this id because we know the room the site is in must be visible
         return Task::Build(construction_site.try_id().unwrap());
     }
 
+    // with nothing else to do, build up the perimeter
+    if let Some((structure, target)) = perimeter_repairs.first() {
+        return Task::Repair(structure.id(), *target);
+    }
+
     Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
 }
 
-fn find_energy_or_source(room: &Room) -> Task {
+fn find_energy_or_source(room: &Room, reservations: &ReservationLedger) -> Task {
     // check for energy on the ground of sufficient quantity to care about
     for resource in room.find(find::DROPPED_RESOURCES, None) {
+        let amount = reservations
+            .unreserved_amount(LogisticsTarget::Resource(resource.id()), resource.amount());
         if resource.resource_type() == ResourceType::Energy
-            && resource.amount() >= BUILDER_ENERGY_PICKUP_THRESHOLD
+            && amount >= BUILDER_ENERGY_PICKUP_THRESHOLD
         {
             return Task::TakeFromResource(resource.id());
         }
@@ -101,15 +163,20 @@ fn find_energy_or_source(room: &Room) -> Task {
             }
         };
 
-        if store.get_used_capacity(Some(ResourceType::Energy)) >= BUILDER_ENERGY_WITHDRAW_THRESHOLD
-        {
-            return Task::TakeFromStructure(structure.as_structure().id(), ResourceType::Energy);
+        let id = structure.as_structure().id();
+        let amount = reservations.unreserved_amount(
+            LogisticsTarget::Structure(id),
+            store.get_used_capacity(Some(ResourceType::Energy)),
+        );
+        if amount >= BUILDER_ENERGY_WITHDRAW_THRESHOLD {
+            return Task::TakeFromStructure(id, ResourceType::Energy);
         }
     }
 
-    // look for sources with energy we can harvest as a last resort
-    if let Some(source) = room.find(find::SOURCES_ACTIVE, None).into_iter().next() {
-        return Task::HarvestEnergyUntilFull(source.id());
+    // look for sources with energy we can harvest as a last resort,
+    // heading to whichever one is closest
+    if !room.find(find::SOURCES_ACTIVE, None).is_empty() {
+        return Task::HarvestEnergyFromClosestSource(room.name());
     }
 
     Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
@@ -1,16 +1,22 @@
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
-    constants::{find, Part, ResourceType},
-    enums::StructureObject,
+    constants::{Part, ResourceType},
     local::RoomName,
-    objects::{Room, Store, Structure, StructureSpawn},
-    prelude::*,
+    objects::Creep,
 };
 
-use crate::{constants::*, game, role::WorkerRole, task::Task, worker::Worker};
+use crate::{
+    body::build_body,
+    constants::*,
+    game,
+    logistics::{LogisticsBroker, PRIORITY_STORE},
+    movement::DangerProfile,
+    role::{lifecycle::renew_or_recycle, WorkerRole},
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Hauler {
@@ -21,124 +27,62 @@ pub struct Hauler {
 }
 
 impl Worker for Hauler {
-    fn find_task(&self, store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
-        match game::rooms().get(self.home_room) {
-            Some(room) => {
-                if store.get_used_capacity(Some(ResourceType::Energy)) > 0 {
-                    find_delivery_target(&room)
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        match context.colony_state.get_mut(&self.home_room) {
+            Some(colony_state) => {
+                let broker = &mut colony_state.logistics;
+                if worker.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                    find_delivery_target(worker, broker)
                 } else {
-                    find_energy(&room)
+                    find_energy(worker, broker)
                 }
             }
             None => {
-                warn!("couldn't see room for task find, must be an orphan");
+                warn!("hauler's home isn't a colony, must be an orphan");
                 Task::IdleUntil(u32::MAX)
             }
         }
     }
 
-    fn get_body_for_creep(&self, spawn: &StructureSpawn) -> Vec<Part> {
-        // scale the creep to larger depending on how much capacity we have available
-        let max_energy_avail = spawn
-            .room()
-            .expect("spawn to have room")
-            .energy_capacity_available();
-        let multiplier = std::cmp::min(
-            max_energy_avail / HAULER_COST_PER_MULTIPLIER,
-            HAULER_MAX_MULTIPLIER,
-        );
-
-        [Part::Carry, Part::Carry, Part::Move].repeat(multiplier as usize)
+    fn find_lifecycle_task(&self, creep: &Creep, context: &mut TaskContext) -> Option<Task> {
+        renew_or_recycle(&WorkerRole::from(*self), creep, self.home_room, context)
     }
-}
 
-fn find_energy(room: &Room) -> Task {
-    // check for energy on the ground of sufficient quantity to care about
-    for resource in room.find(find::DROPPED_RESOURCES, None) {
-        if resource.resource_type() == ResourceType::Energy
-            && resource.amount() >= HAULER_ENERGY_PICKUP_THRESHOLD
-        {
-            return Task::TakeFromResource(resource.id());
-        }
+    // haulers run long routes with soft bodies, keep them away from invaders and keepers
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Cautious
     }
 
-    // check structures - containers and terminals only, don't want
-    // to have these taking from spawns or extensions!
-    for structure in room.find(find::STRUCTURES, None) {
-        let store = match &structure {
-            StructureObject::StructureContainer(o) => o.store(),
-            StructureObject::StructureStorage(o) => o.store(),
-            StructureObject::StructureTerminal(o) => o.store(),
-            _ => {
-                // we don't want to look at this!
-                continue;
-            }
-        };
-
-        if store.get_used_capacity(Some(ResourceType::Energy)) >= HAULER_ENERGY_WITHDRAW_THRESHOLD {
-            return Task::TakeFromStructure(structure.as_structure().id(), ResourceType::Energy);
-        }
+    // haulers keep everything else supplied
+    fn get_path_priority(&self) -> u32 {
+        70
     }
 
-    Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
-}
-
-fn find_delivery_target(room: &Room) -> Task {
-    // check structures - we'll do a pass looking for high priority structures
-    // like spawns and extensions and towers before we check terminal and storage -
-    // but we'll store their references here as we come accoss them
-    let mut maybe_storage = None;
-    let mut maybe_terminal = None;
-
-    for structure in room.find(find::STRUCTURES, None) {
-        let (store, structure) = match structure {
-            // for the three object types that are important to fill, snag their store then cast
-            // them right back to StructureObject
-            StructureObject::StructureSpawn(ref o) => (o.store(), structure),
-            StructureObject::StructureExtension(ref o) => (o.store(), structure),
-            StructureObject::StructureTower(ref o) => (o.store(), structure),
-            // don't want to look at these types in this iteration, in case
-            // one of the covered priority types is later in the vec
-            StructureObject::StructureStorage(o) => {
-                maybe_storage = Some(o);
-                continue;
-            }
-            StructureObject::StructureTerminal(o) => {
-                maybe_terminal = Some(o);
-                continue;
-            }
-            _ => {
-                // we don't want to look at this!
-                continue;
-            }
-        };
-
-        if store.get_free_capacity(Some(ResourceType::Energy)) > 0 {
-            return Task::DeliverToStructure(structure.as_structure().id(), ResourceType::Energy);
-        }
-    }
-
-    // check the terminal if we found one
-    if let Some(terminal) = maybe_terminal {
-        if terminal
-            .store()
-            .get_used_capacity(Some(ResourceType::Energy))
-            < TERMINAL_ENERGY_TARGET
-        {
-            return Task::DeliverToStructure(
-                terminal.id().into_type::<Structure>(),
-                ResourceType::Energy,
-            );
-        }
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part> {
+        build_body(&HAULER_BODY, energy, self.get_movement_profile())
     }
+}
 
-    // and finally check the storage
-    if let Some(storage) = maybe_storage {
-        return Task::DeliverToStructure(
-            storage.id().into_type::<Structure>(),
-            ResourceType::Energy,
-        );
-    }
+// go and collect from the best offer in the colony; stored energy only gets moved when
+// there's somewhere more important than storage that wants it
+fn find_energy(worker: &WorkerReference, broker: &mut LogisticsBroker) -> Task {
+    let min_priority = if broker.has_requests_above(ResourceType::Energy, PRIORITY_STORE) {
+        PRIORITY_STORE
+    } else {
+        PRIORITY_STORE + 1
+    };
+    let capacity = worker.store().get_free_capacity(Some(ResourceType::Energy)) as u32;
+    broker
+        .claim_offer(worker.pos(), ResourceType::Energy, capacity, min_priority)
+        .and_then(|offer| offer.take_task())
+        .unwrap_or(Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS))
+}
 
-    Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
+// take what we're carrying to the best request in the colony
+fn find_delivery_target(worker: &WorkerReference, broker: &mut LogisticsBroker) -> Task {
+    let carrying = worker.store().get_used_capacity(Some(ResourceType::Energy));
+    broker
+        .claim_request(worker.pos(), ResourceType::Energy, carrying)
+        .and_then(|request| request.deliver_task())
+        .unwrap_or(Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS))
 }
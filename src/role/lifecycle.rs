@@ -0,0 +1,82 @@
+use screeps::{
+    constants::{find, Part},
+    enums::StructureObject,
+    game,
+    local::{Position, RoomName},
+    objects::{Creep, Room, StructureSpawn},
+    prelude::*,
+};
+
+use crate::{
+    body::body_cost,
+    constants::*,
+    role::WorkerRole,
+    task::Task,
+    worker::{TaskContext, Worker},
+};
+
+// what an ordinary worker does about getting old or being outgrown: bodies big enough that
+// respawning costs more than topping them up get renewed, and ones well under the size the
+// room can afford now get recycled so that a proper one takes their place. Neither happens
+// while the colony's recovering, since then every creep counts
+pub fn renew_or_recycle(
+    role: &WorkerRole,
+    creep: &Creep,
+    home_room: RoomName,
+    context: &TaskContext,
+) -> Option<Task> {
+    let room = game::rooms().get(home_room)?;
+    if is_recovering(home_room, context) {
+        return None;
+    }
+
+    // only give up an undersized creep once its replacement's on the way, or the room can pay
+    // for one right now - otherwise a jump in capacity from a controller level up would have
+    // every creep in the room recycled at once, with nothing to replace them
+    let body = creep.body();
+    let full_body = role.get_body_for_creep(room.energy_capacity_available());
+    if body.len() * UNDERSIZED_BODY_DIVISOR < full_body.len()
+        && (context.census.count(role) > 1 || body_cost(&full_body) <= room.energy_available())
+    {
+        return recycle(creep, &room);
+    }
+
+    // renewing strips boosts, and creeps with claim parts can't be renewed at all
+    let renewable = body.len() >= RENEW_MIN_BODY_SIZE
+        && body
+            .iter()
+            .all(|part| part.boost().is_none() && part.part() != Part::Claim);
+    // and there needs to be energy to spare, or it'll just be turned away at the spawn
+    let spare_energy = room.energy_available() * 2 >= room.energy_capacity_available();
+    let ticks_to_live = creep.ticks_to_live().unwrap_or(u32::MAX);
+    if renewable && spare_energy && ticks_to_live < RENEW_BELOW_TTL {
+        return closest_spawn(creep.pos(), &room).map(|spawn| Task::RenewAtSpawn(spawn.id()));
+    }
+
+    None
+}
+
+pub fn is_recovering(home_room: RoomName, context: &TaskContext) -> bool {
+    context
+        .colony_state
+        .get(&home_room)
+        .is_some_and(|colony_state| colony_state.recovering)
+}
+
+// head to the closest spawn to be recycled, standing on a container next to it if there is one
+pub fn recycle(creep: &Creep, room: &Room) -> Option<Task> {
+    let spawn = closest_spawn(creep.pos(), room)?;
+    let position = spawn
+        .pos()
+        .find_in_range(find::STRUCTURES, 1)
+        .iter()
+        .find(|structure| matches!(structure, StructureObject::StructureContainer(_)))
+        .map_or(spawn.pos(), |container| container.pos());
+    Some(Task::RecycleAtSpawn(spawn.id(), position))
+}
+
+fn closest_spawn(position: Position, room: &Room) -> Option<StructureSpawn> {
+    room.find(find::MY_SPAWNS, None)
+        .into_iter()
+        .min_by_key(|spawn| position.get_range_to(spawn.pos()))
+}
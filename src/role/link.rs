@@ -0,0 +1,124 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use screeps::{
+    constants::{find, Part, ResourceType},
+    enums::StructureObject,
+    game,
+    local::{Position, RoomName},
+    objects::{Room, StructureLink},
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
+
+// what a link is for, going by what it's next to
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum LinkKind {
+    // filled by a harvester, sends everything on
+    Source,
+    // kept topped up for upgraders to draw from
+    Controller,
+    // next to storage, sends to the controller link when there's no source link to do it
+    Storage,
+    // anywhere else, receives whatever's spare
+    Receiver,
+}
+
+// work out what a link at the given position is for
+pub fn link_kind(room: &Room, pos: Position) -> LinkKind {
+    if room
+        .find(find::SOURCES, None)
+        .iter()
+        .any(|source| source.pos().get_range_to(pos) <= LINK_SOURCE_RANGE)
+    {
+        return LinkKind::Source;
+    }
+    if room
+        .controller()
+        .is_some_and(|controller| controller.pos().get_range_to(pos) <= LINK_CONTROLLER_RANGE)
+    {
+        return LinkKind::Controller;
+    }
+    if room
+        .storage()
+        .is_some_and(|storage| storage.pos().get_range_to(pos) <= LINK_STORAGE_RANGE)
+    {
+        return LinkKind::Storage;
+    }
+    LinkKind::Receiver
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Link {
+    pub room: RoomName,
+    pub kind: LinkKind,
+}
+
+impl Worker for Link {
+    fn find_task(&self, worker: &WorkerReference, _context: &mut TaskContext) -> Task {
+        let store = worker.store();
+        let room = match game::rooms().get(self.room) {
+            Some(room) => room,
+            None => {
+                warn!("couldn't see room for link task find");
+                return Task::IdleUntil(u32::MAX);
+            }
+        };
+
+        // only source and storage links send, and only once they've got enough to be worth
+        // the energy lost in transfer
+        let sends = matches!(self.kind, LinkKind::Source | LinkKind::Storage);
+        if !sends || store.get_used_capacity(Some(ResourceType::Energy)) < LINK_SEND_THRESHOLD {
+            return Task::IdleUntil(game::time() + LINK_IDLE_TICKS);
+        }
+
+        let links: Vec<(StructureLink, LinkKind)> = room
+            .find(find::MY_STRUCTURES, None)
+            .into_iter()
+            .filter_map(|structure| match structure {
+                StructureObject::StructureLink(link) => {
+                    let kind = link_kind(&room, link.pos());
+                    Some((link, kind))
+                }
+                _ => None,
+            })
+            .collect();
+        let free =
+            |link: &StructureLink| link.store().get_free_capacity(Some(ResourceType::Energy));
+        let find_link = |kind: LinkKind, needs: &dyn Fn(&StructureLink) -> bool| {
+            links
+                .iter()
+                .find(|(link, link_kind)| *link_kind == kind && needs(link))
+                .map(|(link, _)| link.id())
+        };
+
+        // the controller link comes first, so upgraders never run dry; then source links
+        // send their surplus to storage, or anywhere else that'll take it
+        let target = find_link(LinkKind::Controller, &|link| {
+            link.store().get_used_capacity(Some(ResourceType::Energy)) < LINK_CONTROLLER_TOP_UP
+        })
+        .or_else(|| match self.kind {
+            LinkKind::Source => find_link(LinkKind::Storage, &|link| free(link) > 0)
+                .or_else(|| find_link(LinkKind::Receiver, &|link| free(link) > 0)),
+            _ => None,
+        });
+
+        match target {
+            Some(target) => Task::TransferEnergyToLink(target),
+            None => Task::IdleUntil(game::time() + LINK_IDLE_TICKS),
+        }
+    }
+
+    fn get_body_for_creep(&self, _energy: u32) -> Vec<Part> {
+        panic!("can't spawn creep for link")
+    }
+
+    fn can_move(&self) -> bool {
+        false
+    }
+}
@@ -1,15 +1,22 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
-    constants::look,
-    constants::Part,
+    constants::{find, look, Part, StructureType},
+    enums::StructureObject,
+    game,
     local::Position,
-    objects::{Store, StructureSpawn},
+    objects::Creep,
     prelude::*,
 };
 
-use crate::{role::WorkerRole, task::Task, worker::Worker};
+use crate::{
+    body::build_body,
+    constants::*,
+    movement::DangerProfile,
+    role::{lifecycle::renew_or_recycle, WorkerRole},
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct SourceHarvester {
@@ -17,19 +24,169 @@ pub struct SourceHarvester {
     pub source_position: Position,
 }
 
+impl SourceHarvester {
+    // the tile to harvest from: the source's container in the blueprint, or failing that a
+    // container that's already been built next to it
+    fn mining_position(&self, context: &TaskContext) -> Option<Position> {
+        let room_name = self.source_position.room_name();
+        let other_resources = self.other_resources();
+
+        let planned = context
+            .colony_state
+            .get(&room_name)
+            .and_then(|colony_state| colony_state.blueprint.as_ref())
+            .and_then(|blueprint| {
+                pick_mining_container(
+                    self.source_position,
+                    blueprint
+                        .structures
+                        .iter()
+                        .filter(|structure| structure.structure_type == StructureType::Container)
+                        .map(|structure| Position::new(structure.xy.x, structure.xy.y, room_name)),
+                    &other_resources,
+                )
+            });
+
+        planned.or_else(|| {
+            pick_mining_container(
+                self.source_position,
+                self.source_position
+                    .find_in_range(find::STRUCTURES, 1)
+                    .iter()
+                    .filter(|structure| matches!(structure, StructureObject::StructureContainer(_)))
+                    .map(|container| container.pos()),
+                &other_resources,
+            )
+        })
+    }
+
+    // the other sources and the mineral in this source's room, if we can see it
+    fn other_resources(&self) -> Vec<Position> {
+        let Some(room) = game::rooms().get(self.source_position.room_name()) else {
+            return vec![];
+        };
+        room.find(find::SOURCES, None)
+            .iter()
+            .map(|source| source.pos())
+            .chain(
+                room.find(find::MINERALS, None)
+                    .iter()
+                    .map(|mineral| mineral.pos()),
+            )
+            .filter(|pos| *pos != self.source_position)
+            .collect()
+    }
+}
+
+// of the containers given, the one next to the source to harvest from; containers that are
+// also next to another source or the mineral most likely belong to that one instead, so
+// they're only used if there's nothing else, and ties go to the top-left-most tile so every
+// tick picks the same one
+fn pick_mining_container(
+    source: Position,
+    containers: impl Iterator<Item = Position>,
+    other_resources: &[Position],
+) -> Option<Position> {
+    containers
+        .filter(|pos| pos.get_range_to(source) == 1)
+        .min_by_key(|pos| {
+            let shared = other_resources
+                .iter()
+                .any(|resource| pos.get_range_to(*resource) == 1);
+            (shared, pos.y(), pos.x())
+        })
+}
+
 impl Worker for SourceHarvester {
-    fn find_task(&self, _store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
+    fn find_task(&self, _worker: &WorkerReference, context: &mut TaskContext) -> Task {
         match self.source_position.look_for(look::SOURCES) {
             Ok(sources) => match sources.first() {
-                Some(source) => Task::HarvestEnergyForever(source.id()),
+                Some(source) => match self.mining_position(context) {
+                    Some(mining_position) => {
+                        Task::HarvestEnergyStatic(source.id(), mining_position)
+                    }
+                    // nowhere to put a container, so just drop the energy by the source
+                    None => Task::HarvestEnergyForever(source.id()),
+                },
                 None => Task::MoveToPosition(self.source_position, 1),
             },
             Err(_) => Task::MoveToPosition(self.source_position, 1),
         }
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
-        use Part::*;
-        vec![Move, Move, Move, Work, Work, Work, Work, Work]
+    fn find_lifecycle_task(&self, creep: &Creep, context: &mut TaskContext) -> Option<Task> {
+        renew_or_recycle(
+            &WorkerRole::from(*self),
+            creep,
+            self.source_position.room_name(),
+            context,
+        )
+    }
+
+    // harvesters are slow and stand still once they arrive, so they can't outrun anything -
+    // keep well clear of whatever could reach them
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Avoid
+    }
+
+    // a harvester not at its source is income lost every tick
+    fn get_path_priority(&self) -> u32 {
+        90
+    }
+
+    fn get_work_position(&self) -> Option<Position> {
+        Some(self.source_position)
+    }
+
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part> {
+        build_body(&SOURCE_HARVESTER_BODY, energy, self.get_movement_profile())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use screeps::local::{RoomCoordinate, RoomName};
+
+    use super::*;
+
+    fn pos(x: u8, y: u8) -> Position {
+        Position::new(
+            RoomCoordinate::try_from(x).unwrap(),
+            RoomCoordinate::try_from(y).unwrap(),
+            RoomName::new("W1N1").unwrap(),
+        )
+    }
+
+    #[test]
+    fn mining_container_skips_ones_shared_with_other_resources() {
+        let source = pos(10, 10);
+        let other_source = pos(12, 10);
+        // (11, 9) is next to both sources, (9, 11) only to ours, and (20, 20) to neither
+        let containers = [pos(11, 9), pos(20, 20), pos(9, 11)];
+
+        assert_eq!(
+            pick_mining_container(source, containers.into_iter(), &[other_source]),
+            Some(pos(9, 11))
+        );
+        // a shared container is still better than none
+        assert_eq!(
+            pick_mining_container(source, [pos(11, 9)].into_iter(), &[other_source]),
+            Some(pos(11, 9))
+        );
+        assert_eq!(
+            pick_mining_container(source, [pos(20, 20)].into_iter(), &[other_source]),
+            None
+        );
+    }
+
+    #[test]
+    fn mining_container_choice_ignores_order() {
+        let source = pos(10, 10);
+        let containers = [pos(11, 11), pos(9, 11), pos(11, 9)];
+
+        let forward = pick_mining_container(source, containers.into_iter(), &[]);
+        let reverse = pick_mining_container(source, containers.into_iter().rev(), &[]);
+        assert_eq!(forward, Some(pos(11, 9)));
+        assert_eq!(forward, reverse);
     }
 }
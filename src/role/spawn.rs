@@ -1,26 +1,135 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+
+use log::*;
 
 use screeps::{
-    constants::find,
-    constants::Part,
+    constants::{find, Part, CREEP_SPAWN_TIME},
     game,
     local::RoomName,
-    objects::{Store, StructureSpawn},
+    objects::Room,
     prelude::*,
 };
 
-use crate::{constants::*, role::*, task::Task};
+use crate::{body::body_cost, constants::*, role::*, task::Task};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Spawn {
     pub room: RoomName,
 }
 
-impl Worker for Spawn {
-    fn find_task(&self, _store: &Store, worker_roles: &HashSet<WorkerRole>) -> Task {
-        // for each role variant we want a creep occupying, check
-        // if a worker exists; if not, that's the creep we'll pick to spawn next
+// which of the roles that can refill spawns have creeps alive (or spawning) in a colony
+#[derive(Debug, Default, Clone, Copy)]
+struct Refillers {
+    haulers: bool,
+    harvesters: bool,
+    startups: bool,
+}
+
+// the colony goes into recovery when nothing's alive to refill the spawns and there isn't
+// enough energy in the room for a full size hauler, and comes out once what refills them at
+// its level is back: a hauler and a harvester, or below level 3 where neither is spawned,
+// startups
+fn recovery_state(
+    recovering: bool,
+    room_level: u8,
+    refillers: Refillers,
+    can_afford_hauler: bool,
+) -> bool {
+    if recovering {
+        let rebuilt = if room_level < 3 {
+            refillers.startups
+        } else {
+            refillers.haulers && refillers.harvesters
+        };
+        !rebuilt
+    } else {
+        !refillers.haulers && !refillers.startups && !can_afford_hauler
+    }
+}
+
+impl Spawn {
+    // keep the colony's recovery state up to date with what's alive in it; returns whether
+    // it's recovering
+    fn update_recovery(&self, room: &Room, room_level: u8, context: &mut TaskContext) -> bool {
+        let mut refillers = Refillers::default();
+        for role in context.census.roles() {
+            match role {
+                WorkerRole::Hauler(hauler) if hauler.home_room == self.room => {
+                    refillers.haulers = true
+                }
+                WorkerRole::Startup(startup) if startup.home_room == self.room => {
+                    refillers.startups = true
+                }
+                WorkerRole::SourceHarvester(harvester)
+                    if harvester.source_position.room_name() == self.room =>
+                {
+                    refillers.harvesters = true
+                }
+                _ => {}
+            }
+        }
+
+        let colony_state = match context.colony_state.get_mut(&self.room) {
+            Some(colony_state) => colony_state,
+            None => return false,
+        };
+        let hauler_role = WorkerRole::Hauler(Hauler {
+            home_room: self.room,
+            id: 0,
+        });
+        let can_afford_hauler = room.energy_available()
+            >= body_cost(&hauler_role.get_body_for_creep(room.energy_capacity_available()));
+
+        let recovering = recovery_state(
+            colony_state.recovering,
+            room_level,
+            refillers,
+            can_afford_hauler,
+        );
+        if recovering && !colony_state.recovering {
+            warn!(
+                "{} has nothing to refill spawns, entering recovery",
+                self.room
+            );
+        } else if !recovering && colony_state.recovering {
+            info!("{} economy rebuilt, leaving recovery", self.room);
+        }
+        colony_state.recovering = recovering;
+        recovering
+    }
+
+    // whether to spawn for a role: either nothing's filling it, or its creep will expire
+    // before a replacement could be spawned and walk over to where it works
+    fn needs_creep(
+        &self,
+        worker: &WorkerReference,
+        room: &Room,
+        role: &WorkerRole,
+        context: &mut TaskContext,
+    ) -> bool {
+        let expires = match context.census.latest_expiry(role) {
+            Some(expires) => expires,
+            None => return true,
+        };
+        let spawn_time = role
+            .get_body_for_creep(room.energy_capacity_available())
+            .len() as u32
+            * CREEP_SPAWN_TIME;
+        let travel_time = role
+            .get_work_position()
+            .zip(context.colony_state.get_mut(&self.room))
+            .map_or(0, |(work_position, colony_state)| {
+                colony_state.travel_time(worker.pos(), work_position)
+            });
+        expires <= game::time() + spawn_time + travel_time
+    }
+}
+
+impl Spawn {
+    // every creep the colony wants spawned right now, most important first: for each role
+    // variant we want a creep occupying, check if a worker exists or is due replacing
+    fn find_wanted_creeps(&self, worker: &WorkerReference, context: &mut TaskContext) -> Vec<Task> {
+        let mut wanted = vec![];
 
         let room = game::rooms()
             .get(self.room)
@@ -30,6 +139,20 @@ impl Worker for Spawn {
             .expect("expected controller in room with spawn")
             .level();
 
+        // if the economy's collapsed, get cheap creeps out with whatever energy there is to
+        // start refilling, before going back to spawning at full size
+        if self.update_recovery(&room, room_level, context) {
+            for i in 0..STARTUP_RECOVERY_COUNT_TARGET {
+                let startup_role = WorkerRole::Startup(Startup {
+                    home_room: self.room,
+                    id: i,
+                });
+                if self.needs_creep(worker, &room, &startup_role, context) {
+                    want(&mut wanted, Task::SpawnRecoveryCreep(startup_role));
+                }
+            }
+        }
+
         if room_level < 3 {
             // just make sure there's a bunch of startup creeps or else return idle
             for i in 0..STARTUP_RCL1_COUNT_TARGET {
@@ -38,13 +161,13 @@ impl Worker for Spawn {
                     home_room: self.room,
                     id: i,
                 });
-                if !worker_roles.contains(&startup_role) {
-                    return Task::SpawnCreep(startup_role);
+                if self.needs_creep(worker, &room, &startup_role, context) {
+                    want(&mut wanted, Task::SpawnCreep(startup_role));
                 }
             }
 
-            // we only want starter creeps, idle
-            return Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS);
+            // we only want starter creeps
+            return wanted;
         }
 
         // crap - how we gonna get a watermark from colony state from here (or update it)
@@ -67,8 +190,8 @@ impl Worker for Spawn {
             let harvester_role = WorkerRole::SourceHarvester(SourceHarvester {
                 source_position: source.pos(),
             });
-            if !worker_roles.contains(&harvester_role) {
-                return Task::SpawnCreep(harvester_role);
+            if self.needs_creep(worker, &room, &harvester_role, context) {
+                want(&mut wanted, Task::SpawnCreep(harvester_role));
             }
         }
 
@@ -102,8 +225,8 @@ impl Worker for Spawn {
                 home_room: self.room,
                 repair_watermark,
             });
-            if !worker_roles.contains(&builder_role) {
-                return Task::SpawnCreep(builder_role);
+            if self.needs_creep(worker, &room, &builder_role, context) {
+                want(&mut wanted, Task::SpawnCreep(builder_role));
             }
         }
 
@@ -113,8 +236,8 @@ impl Worker for Spawn {
                 home_room: self.room,
                 id: i,
             });
-            if !worker_roles.contains(&hauler_role) {
-                return Task::SpawnCreep(hauler_role);
+            if self.needs_creep(worker, &room, &hauler_role, context) {
+                want(&mut wanted, Task::SpawnCreep(hauler_role));
             }
         }
 
@@ -124,16 +247,97 @@ impl Worker for Spawn {
                 home_room: self.room,
                 id: i,
             });
-            if !worker_roles.contains(&upgrader_role) {
-                return Task::SpawnCreep(upgrader_role);
+            if self.needs_creep(worker, &room, &upgrader_role, context) {
+                want(&mut wanted, Task::SpawnCreep(upgrader_role));
+            }
+        }
+
+        wanted
+    }
+}
+
+impl Worker for Spawn {
+    // take the most important creep from the colony's queue that this is the closest free
+    // spawn for, leaving the rest for the colony's other spawns
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        let spawn = match worker {
+            WorkerReference::Spawn(spawn) => spawn,
+            _ => panic!("unsupported worker type!"),
+        };
+        if let Some(spawning) = spawn.spawning() {
+            return Task::IdleUntil(game::time() + spawning.remaining_time());
+        }
+        let spawn_id = spawn.id();
+
+        // rebuild the queue once a tick, for whichever spawn gets here first; creeps another
+        // spawn has already taken on are left out
+        let stale = context
+            .colony_state
+            .get(&self.room)
+            .is_some_and(|colony_state| colony_state.spawn_queue_tick != game::time());
+        if stale {
+            let wanted = self.find_wanted_creeps(worker, context);
+            if let Some(colony_state) = context.colony_state.get_mut(&self.room) {
+                colony_state
+                    .spawn_claims
+                    .retain(|id, _| *id != spawn_id && id.resolve().is_some());
+                colony_state.spawn_queue = wanted
+                    .into_iter()
+                    .filter(|task| {
+                        !colony_state
+                            .spawn_claims
+                            .values()
+                            .any(|claimed| Some(claimed) == spawned_role(task))
+                    })
+                    .collect();
+                colony_state.spawn_queue_tick = game::time();
             }
         }
 
-        // last resort, idle
-        Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
+        let colony_state = match context.colony_state.get_mut(&self.room) {
+            Some(colony_state) => colony_state,
+            None => return Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS),
+        };
+        // we're looking for something new to spawn, so whatever we took on before is done
+        colony_state.spawn_claims.remove(&spawn_id);
+
+        let room = game::rooms()
+            .get(self.room)
+            .expect("expected room for active spawn");
+        let free_spawns: Vec<_> = room
+            .find(find::MY_SPAWNS, None)
+            .into_iter()
+            .filter(|other| {
+                other.spawning().is_none()
+                    && (other.id() == spawn_id
+                        || !colony_state.spawn_claims.contains_key(&other.id()))
+            })
+            .collect();
+
+        let claimed = colony_state.spawn_queue.iter().position(|task| {
+            // creeps without a particular place to work can come from any spawn
+            let work_position = match spawned_role(task).and_then(|role| role.get_work_position()) {
+                Some(work_position) => work_position,
+                None => return true,
+            };
+            free_spawns
+                .iter()
+                .min_by_key(|other| other.pos().get_range_to(work_position))
+                .is_none_or(|closest| closest.id() == spawn_id)
+        });
+        match claimed {
+            Some(index) => {
+                let task = colony_state.spawn_queue.remove(index);
+                if let Some(role) = spawned_role(&task) {
+                    colony_state.spawn_claims.insert(spawn_id, *role);
+                }
+                task
+            }
+            None => Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS),
+        }
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
+    fn get_body_for_creep(&self, _energy: u32) -> Vec<Part> {
         panic!("can't spawn creep for spawn")
     }
 
@@ -141,3 +345,81 @@ impl Worker for Spawn {
         false
     }
 }
+
+// the role a spawn task is for
+fn spawned_role(task: &Task) -> Option<&WorkerRole> {
+    match task {
+        Task::SpawnCreep(role) | Task::SpawnRecoveryCreep(role) => Some(role),
+        _ => None,
+    }
+}
+
+// add a creep to the wanted list, unless it's already on there
+fn want(wanted: &mut Vec<Task>, task: Task) {
+    if !wanted
+        .iter()
+        .any(|existing| spawned_role(existing) == spawned_role(&task))
+    {
+        wanted.push(task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_is_left_at_rcl2_once_startups_are_back() {
+        // everything's died and there's not enough energy for a hauler
+        let nothing = Refillers::default();
+        let recovering = recovery_state(false, 2, nothing, false);
+        assert!(recovering);
+        // still nothing while the first recovery creeps get going
+        let recovering = recovery_state(recovering, 2, nothing, false);
+        assert!(recovering);
+
+        // haulers and harvesters aren't spawned below level 3, so startups are enough
+        let startups = Refillers {
+            startups: true,
+            ..Refillers::default()
+        };
+        let recovering = recovery_state(recovering, 2, startups, false);
+        assert!(!recovering);
+        // and with startups alive, it doesn't go straight back in
+        assert!(!recovery_state(recovering, 2, startups, false));
+    }
+
+    #[test]
+    fn recovery_at_rcl3_waits_for_haulers_and_harvesters() {
+        let startups = Refillers {
+            startups: true,
+            ..Refillers::default()
+        };
+        assert!(recovery_state(true, 3, startups, true));
+
+        let haulers = Refillers {
+            haulers: true,
+            startups: true,
+            ..Refillers::default()
+        };
+        assert!(recovery_state(true, 3, haulers, true));
+
+        let rebuilt = Refillers {
+            haulers: true,
+            harvesters: true,
+            startups: false,
+        };
+        assert!(!recovery_state(true, 3, rebuilt, false));
+    }
+
+    #[test]
+    fn recovery_not_entered_while_spawns_can_be_refilled() {
+        let haulers = Refillers {
+            haulers: true,
+            ..Refillers::default()
+        };
+        assert!(!recovery_state(false, 4, haulers, false));
+        // or while there's energy for a proper hauler anyway
+        assert!(!recovery_state(false, 4, Refillers::default(), true));
+    }
+}
@@ -1,18 +1,27 @@
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
     constants::{find, Part, ResourceType},
     enums::StructureObject,
     game,
     local::RoomName,
-    objects::{Room, Store, StructureSpawn},
+    objects::{Creep, Room},
     prelude::*,
 };
 
 use crate::{
-    constants::*, movement::MovementProfile, role::WorkerRole, task::Task, worker::Worker,
+    body::build_body,
+    constants::*,
+    logistics::LogisticsTarget,
+    movement::{DangerProfile, MovementProfile},
+    reservation::ReservationLedger,
+    role::{
+        lifecycle::{is_recovering, recycle},
+        WorkerRole,
+    },
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
 };
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
@@ -24,13 +33,14 @@ pub struct Startup {
 }
 
 impl Worker for Startup {
-    fn find_task(&self, store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        let store = worker.store();
         match game::rooms().get(self.home_room) {
             Some(room) => {
                 if store.get_used_capacity(Some(ResourceType::Energy)) > 0 {
-                    find_startup_task(&room)
+                    find_startup_task(worker, &room, context.reservations)
                 } else {
-                    find_energy_or_source(&room)
+                    find_energy_or_source(&room, context.reservations)
                 }
             }
             None => {
@@ -40,34 +50,75 @@ impl Worker for Startup {
         }
     }
 
+    // once the colony has haulers and has grown out of needing these, they're recycled, but
+    // they're kept on while it's recovering
+    fn find_lifecycle_task(&self, creep: &Creep, context: &mut TaskContext) -> Option<Task> {
+        let room = game::rooms().get(self.home_room)?;
+        let level = room.controller().map_or(0, |controller| controller.level());
+        let haulers = context.census.roles().any(
+            |role| matches!(role, WorkerRole::Hauler(hauler) if hauler.home_room == self.home_room),
+        );
+        if level >= 3 && haulers && !is_recovering(self.home_room, context) {
+            recycle(creep, &room)
+        } else {
+            None
+        }
+    }
+
     fn get_movement_profile(&self) -> MovementProfile {
         MovementProfile::PlainsOneToOne
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
-        use Part::*;
-        vec![Move, Move, Carry, Work]
+    // losing one of these early on can stall the colony, so stay well clear of hostiles
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Avoid
+    }
+
+    // early on, these creeps are the whole economy
+    fn get_path_priority(&self) -> u32 {
+        80
+    }
+
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part> {
+        build_body(&STARTUP_BODY, energy, self.get_movement_profile())
     }
 }
 
-fn find_startup_task(room: &Room) -> Task {
-    // look for supply tasks a spawn or extension
+fn find_startup_task(
+    worker: &WorkerReference,
+    room: &Room,
+    reservations: &ReservationLedger,
+) -> Task {
+    // look for supply tasks a spawn or extension, heading for whichever closest one
+    // isn't already being filled by someone else
+    let pos = worker.pos();
+    let mut closest = None;
     for structure in room.find(find::STRUCTURES, None) {
-        let (store, structure) = match structure {
-            // for the three object types that are important to fill, snag their store then cast
-            // them right back to StructureObject
-            StructureObject::StructureSpawn(ref o) => (o.store(), structure),
-            StructureObject::StructureExtension(ref o) => (o.store(), structure),
+        let store = match &structure {
+            // for the two object types that are important to fill, snag their store
+            StructureObject::StructureSpawn(o) => o.store(),
+            StructureObject::StructureExtension(o) => o.store(),
             _ => {
                 // no need to deliver to any other structures with these little ones
                 continue;
             }
         };
 
-        if store.get_free_capacity(Some(ResourceType::Energy)) > 0 {
-            return Task::DeliverToStructure(structure.as_structure().id(), ResourceType::Energy);
+        let id = structure.as_structure().id();
+        let capacity = reservations.unreserved_capacity(
+            LogisticsTarget::Structure(id),
+            store.get_free_capacity(Some(ResourceType::Energy)).max(0) as u32,
+        );
+        if capacity > 0 {
+            let range = pos.get_range_to(structure.pos());
+            if closest.is_none_or(|(closest_range, _)| range < closest_range) {
+                closest = Some((range, id));
+            }
         }
     }
+    if let Some((_, id)) = closest {
+        return Task::DeliverToStructure(id, ResourceType::Energy);
+    }
 
     // look for repair tasks
     // note that we're using STRUCTURES instead of MY_STRUCTURES
@@ -84,7 +135,7 @@ fn find_startup_task(room: &Room) -> Task {
             // if the hits are below our 'watermark' to repair to
             // as well as less than half of this struture's max, repair!
             if hits < 10_000 && hits * 2 < hits_max {
-                return Task::Repair(structure.id());
+                return Task::Repair(structure.id(), hits_max);
             }
         }
     }
@@ -107,11 +158,13 @@ fn find_startup_task(room: &Room) -> Task {
     Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
 }
 
-fn find_energy_or_source(room: &Room) -> Task {
+fn find_energy_or_source(room: &Room, reservations: &ReservationLedger) -> Task {
     // check for energy on the ground of sufficient quantity to care about
     for resource in room.find(find::DROPPED_RESOURCES, None) {
+        let amount = reservations
+            .unreserved_amount(LogisticsTarget::Resource(resource.id()), resource.amount());
         if resource.resource_type() == ResourceType::Energy
-            && resource.amount() >= BUILDER_ENERGY_PICKUP_THRESHOLD
+            && amount >= BUILDER_ENERGY_PICKUP_THRESHOLD
         {
             return Task::TakeFromResource(resource.id());
         }
@@ -130,15 +183,20 @@ fn find_energy_or_source(room: &Room) -> Task {
             }
         };
 
-        if store.get_used_capacity(Some(ResourceType::Energy)) >= BUILDER_ENERGY_WITHDRAW_THRESHOLD
-        {
-            return Task::TakeFromStructure(structure.as_structure().id(), ResourceType::Energy);
+        let id = structure.as_structure().id();
+        let amount = reservations.unreserved_amount(
+            LogisticsTarget::Structure(id),
+            store.get_used_capacity(Some(ResourceType::Energy)),
+        );
+        if amount >= BUILDER_ENERGY_WITHDRAW_THRESHOLD {
+            return Task::TakeFromStructure(id, ResourceType::Energy);
         }
     }
 
-    // look for sources with energy we can harvest as a last resort
-    if let Some(source) = room.find(find::SOURCES_ACTIVE, None).into_iter().next() {
-        return Task::HarvestEnergyUntilFull(source.id());
+    // look for sources with energy we can harvest as a last resort,
+    // heading to whichever one is closest
+    if !room.find(find::SOURCES_ACTIVE, None).is_empty() {
+        return Task::HarvestEnergyFromClosestSource(room.name());
     }
 
     Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
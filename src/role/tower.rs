@@ -1,13 +1,20 @@
+use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
-    constants::Part,
+    constants::{find, Part, ResourceType},
+    game,
     local::RoomName,
-    objects::{Store, StructureSpawn},
+    prelude::*,
 };
 
-use crate::{role::WorkerRole, task::Task, worker::Worker};
+use crate::{
+    constants::*,
+    construction::perimeter_repairs,
+    movement::is_ally,
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Tower {
@@ -15,11 +22,52 @@ pub struct Tower {
 }
 
 impl Worker for Tower {
-    fn find_task(&self, _store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
-        unimplemented!()
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        let store = worker.store();
+        let room = match game::rooms().get(self.room) {
+            Some(room) => room,
+            None => {
+                warn!("couldn't see room for tower task find");
+                return Task::IdleUntil(u32::MAX);
+            }
+        };
+
+        // shoot whatever's hostile first, with every tower on the weakest so they finish it off
+        if let Some(hostile) = room
+            .find(find::HOSTILE_CREEPS, None)
+            .into_iter()
+            .filter(|creep| !is_ally(&creep.owner().username()))
+            .min_by_key(|creep| creep.hits())
+        {
+            return Task::AttackCreep(hostile.try_id().unwrap());
+        }
+
+        // then patch up our own creeps, most hurt first
+        if let Some(hurt) = room
+            .find(find::MY_CREEPS, None)
+            .into_iter()
+            .filter(|creep| creep.hits() < creep.hits_max())
+            .min_by_key(|creep| creep.hits() as u64 * 1_000 / creep.hits_max() as u64)
+        {
+            return Task::HealCreep(hurt.try_id().unwrap());
+        }
+
+        // with energy to spare, keep the perimeter up to its targets
+        if store.get_used_capacity(Some(ResourceType::Energy)) >= TOWER_REPAIR_ENERGY_RESERVE {
+            let repair = context
+                .colony_state
+                .get(&self.room)
+                .and_then(|colony_state| colony_state.blueprint.as_ref())
+                .and_then(|blueprint| perimeter_repairs(&room, blueprint).into_iter().next());
+            if let Some((structure, target)) = repair {
+                return Task::Repair(structure.id(), target);
+            }
+        }
+
+        Task::IdleUntil(game::time() + TOWER_IDLE_TICKS)
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
+    fn get_body_for_creep(&self, _energy: u32) -> Vec<Part> {
         panic!("can't spawn creep for tower")
     }
 
@@ -1,17 +1,26 @@
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 use screeps::{
     constants::{find, Part, ResourceType},
     enums::StructureObject,
     game,
-    local::RoomName,
-    objects::{Room, Store, StructureSpawn},
+    local::{Position, RoomName},
+    objects::{Creep, Room},
     prelude::*,
 };
 
-use crate::{constants::*, role::WorkerRole, task::Task, worker::Worker};
+use crate::{
+    body::build_body,
+    constants::*,
+    logistics::{LogisticsBroker, LogisticsEntry, LogisticsTarget, PRIORITY_WORKER},
+    movement::DangerProfile,
+    reservation::ReservationLedger,
+    role::{lifecycle::renew_or_recycle, WorkerRole},
+    role::{link_kind, LinkKind},
+    task::Task,
+    worker::{TaskContext, Worker, WorkerReference},
+};
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Upgrader {
@@ -22,13 +31,14 @@ pub struct Upgrader {
 }
 
 impl Worker for Upgrader {
-    fn find_task(&self, store: &Store, _worker_roles: &HashSet<WorkerRole>) -> Task {
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task {
+        let store = worker.store();
         match game::rooms().get(self.home_room) {
             Some(room) => {
                 if store.get_used_capacity(Some(ResourceType::Energy)) > 0 {
                     find_upgrade_task(&room)
                 } else {
-                    find_energy_or_source(&room)
+                    find_energy_or_source(&room, context.reservations)
                 }
             }
             None => {
@@ -38,9 +48,62 @@ impl Worker for Upgrader {
         }
     }
 
-    fn get_body_for_creep(&self, _spawn: &StructureSpawn) -> Vec<Part> {
-        use Part::*;
-        vec![Move, Move, Carry, Work]
+    // ask for energy to be brought over while we're at the controller, so we can keep
+    // upgrading instead of walking off to fetch it
+    fn post_logistics(
+        &self,
+        worker: &WorkerReference,
+        broker: &mut LogisticsBroker,
+        reservations: &ReservationLedger,
+    ) {
+        if let WorkerReference::Creep(creep) = worker {
+            let store = creep.store();
+            let free = store.get_free_capacity(Some(ResourceType::Energy)) as u32;
+            let at_controller =
+                creep
+                    .room()
+                    .and_then(|room| room.controller())
+                    .is_some_and(|controller| {
+                        creep.pos().get_range_to(controller.pos()) <= UPGRADER_REQUEST_RANGE
+                    });
+            if at_controller && free * 2 >= store.get_capacity(Some(ResourceType::Energy)) {
+                broker.request(
+                    LogisticsEntry {
+                        target: LogisticsTarget::Creep(creep.try_id().unwrap()),
+                        pos: creep.pos(),
+                        resource_type: ResourceType::Energy,
+                        amount: free,
+                        priority: PRIORITY_WORKER,
+                    },
+                    reservations,
+                );
+            }
+        }
+    }
+
+    fn find_lifecycle_task(&self, creep: &Creep, context: &mut TaskContext) -> Option<Task> {
+        renew_or_recycle(&WorkerRole::from(*self), creep, self.home_room, context)
+    }
+
+    // upgraders stay home, but should still step around anything that's made it into the room
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Cautious
+    }
+
+    // upgraders can afford to wait their turn
+    fn get_path_priority(&self) -> u32 {
+        30
+    }
+
+    fn get_work_position(&self) -> Option<Position> {
+        game::rooms()
+            .get(self.home_room)
+            .and_then(|room| room.controller())
+            .map(|controller| controller.pos())
+    }
+
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part> {
+        build_body(&UPGRADER_BODY, energy, self.get_movement_profile())
     }
 }
 
@@ -52,16 +115,32 @@ fn find_upgrade_task(room: &Room) -> Task {
     }
 }
 
-fn find_energy_or_source(room: &Room) -> Task {
+fn find_energy_or_source(room: &Room, reservations: &ReservationLedger) -> Task {
     // check for energy on the ground of sufficient quantity to care about
     for resource in room.find(find::DROPPED_RESOURCES, None) {
+        let amount = reservations
+            .unreserved_amount(LogisticsTarget::Resource(resource.id()), resource.amount());
         if resource.resource_type() == ResourceType::Energy
-            && resource.amount() >= UPGRADER_ENERGY_PICKUP_THRESHOLD
+            && amount >= UPGRADER_ENERGY_PICKUP_THRESHOLD
         {
             return Task::TakeFromResource(resource.id());
         }
     }
 
+    // the controller link is kept topped up for us, right where we work
+    for structure in room.find(find::MY_STRUCTURES, None) {
+        if let StructureObject::StructureLink(link) = &structure {
+            let id = structure.as_structure().id();
+            let amount = reservations.unreserved_amount(
+                LogisticsTarget::Structure(id),
+                link.store().get_used_capacity(Some(ResourceType::Energy)),
+            );
+            if amount > 0 && link_kind(room, link.pos()) == LinkKind::Controller {
+                return Task::TakeFromStructure(id, ResourceType::Energy);
+            }
+        }
+    }
+
     // check structures - filtering for certain types, don't want
     // to have these taking from spawns or extensions!
     for structure in room.find(find::STRUCTURES, None) {
@@ -75,15 +154,20 @@ fn find_energy_or_source(room: &Room) -> Task {
             }
         };
 
-        if store.get_used_capacity(Some(ResourceType::Energy)) >= UPGRADER_ENERGY_WITHDRAW_THRESHOLD
-        {
-            return Task::TakeFromStructure(structure.as_structure().id(), ResourceType::Energy);
+        let id = structure.as_structure().id();
+        let amount = reservations.unreserved_amount(
+            LogisticsTarget::Structure(id),
+            store.get_used_capacity(Some(ResourceType::Energy)),
+        );
+        if amount >= UPGRADER_ENERGY_WITHDRAW_THRESHOLD {
+            return Task::TakeFromStructure(id, ResourceType::Energy);
         }
     }
 
-    // look for sources with energy we can harvest as a last resort
-    if let Some(source) = room.find(find::SOURCES_ACTIVE, None).into_iter().next() {
-        return Task::HarvestEnergyUntilFull(source.id());
+    // look for sources with energy we can harvest as a last resort,
+    // heading to whichever one is closest
+    if !room.find(find::SOURCES_ACTIVE, None).is_empty() {
+        return Task::HarvestEnergyFromClosestSource(room.name());
     }
 
     Task::IdleUntil(game::time() + NO_TASK_IDLE_TICKS)
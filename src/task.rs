@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use screeps::{
     constants::ResourceType,
     game,
-    local::{ObjectId, Position},
+    local::{ObjectId, Position, RoomName},
     objects::*,
 };
 
@@ -14,13 +14,19 @@ use crate::{
 };
 
 mod build;
+mod defense;
+mod flee;
 mod harvest;
+mod lifecycle;
+mod link;
 mod logistics;
 mod repair;
 mod spawn;
 mod upgrade;
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub use flee::should_retreat;
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum TaskResult {
     Complete,
     StillWorking,
@@ -37,14 +43,34 @@ pub enum Task {
     MoveToPosition(Position, u32),
     HarvestEnergyUntilFull(ObjectId<Source>),
     HarvestEnergyForever(ObjectId<Source>),
+    // harvest from the given tile, keeping the container there built and repaired
+    HarvestEnergyStatic(ObjectId<Source>, Position),
     Build(ObjectId<ConstructionSite>),
-    Repair(ObjectId<Structure>),
+    // repair until the structure reaches the given hits
+    Repair(ObjectId<Structure>, u32),
     Upgrade(ObjectId<StructureController>),
     TakeFromResource(ObjectId<Resource>),
     TakeFromStructure(ObjectId<Structure>, ResourceType),
     DeliverToStructure(ObjectId<Structure>, ResourceType),
+    DeliverToCreep(ObjectId<Creep>, ResourceType),
     SpawnCreep(WorkerRole),
+    // spawn with whatever energy the room has right now, instead of waiting for it to fill
+    SpawnRecoveryCreep(WorkerRole),
     WaitToSpawn,
+    // get away from hostiles until the tick; given a position, head there as well when there's
+    // nothing in sight to run from, instead of finishing
+    Retreat(u32, Option<Position>),
+    FleeFromPosition(Position, u32, u32),
+    HarvestEnergyFromClosestSource(RoomName),
+    DeliverEnergyToClosestFillable(RoomName),
+    // only for inter-shard portals, since it never completes on this shard
+    MoveThroughPortal(Position),
+    AttackCreep(ObjectId<Creep>),
+    HealCreep(ObjectId<Creep>),
+    TransferEnergyToLink(ObjectId<StructureLink>),
+    RenewAtSpawn(ObjectId<StructureSpawn>),
+    // recycle at the spawn, from the given tile next to it
+    RecycleAtSpawn(ObjectId<StructureSpawn>, Position),
 }
 
 impl Task {
@@ -66,14 +92,14 @@ impl Task {
                 if worker.pos().get_range_to(*position) <= *range {
                     TaskResult::Complete
                 } else {
-                    TaskResult::MoveMeTo(MovementGoal {
-                        pos: *position,
-                        range: *range,
-                        profile: movement_profile,
-                        avoid_creeps: false,
-                    })
+                    TaskResult::MoveMeTo(MovementGoal::new(*position, *range, movement_profile))
                 }
             }
+            // step onto the portal; once we're through, this worker won't resolve any more
+            // (or on another shard, picks the rest of its queue back up from the handoff)
+            Task::MoveThroughPortal(position) => {
+                TaskResult::MoveMeTo(MovementGoal::new(*position, 0, movement_profile))
+            }
             // remaining task types are more complex and have handlers
             Task::HarvestEnergyUntilFull(id) => {
                 harvest::harvest_energy_until_full(worker, id, movement_profile)
@@ -81,8 +107,11 @@ impl Task {
             Task::HarvestEnergyForever(id) => {
                 harvest::harvest_energy_forever(worker, id, movement_profile)
             }
+            Task::HarvestEnergyStatic(id, position) => {
+                harvest::harvest_energy_static(worker, id, *position, movement_profile)
+            }
             Task::Build(id) => build::build(worker, id, movement_profile),
-            Task::Repair(id) => repair::repair(worker, id, movement_profile),
+            Task::Repair(id, hits) => repair::repair(worker, id, *hits, movement_profile),
             Task::Upgrade(id) => upgrade::upgrade(worker, id, movement_profile),
             Task::TakeFromResource(id) => {
                 logistics::take_from_resource(worker, id, movement_profile)
@@ -93,8 +122,31 @@ impl Task {
             Task::DeliverToStructure(id, ty) => {
                 logistics::deliver_to_structure(worker, *id, *ty, movement_profile)
             }
-            Task::SpawnCreep(role) => spawn::spawn_creep(worker, role),
+            Task::DeliverToCreep(id, ty) => {
+                logistics::deliver_to_creep(worker, id, *ty, movement_profile)
+            }
+            Task::SpawnCreep(role) => spawn::spawn_creep(worker, role, false),
+            Task::SpawnRecoveryCreep(role) => spawn::spawn_creep(worker, role, true),
             Task::WaitToSpawn => spawn::wait_to_spawn(worker),
+            Task::Retreat(tick, fallback) => {
+                flee::retreat(worker, *tick, *fallback, movement_profile)
+            }
+            Task::FleeFromPosition(position, range, tick) => {
+                flee::flee_from_position(worker, *position, *range, *tick, movement_profile)
+            }
+            Task::HarvestEnergyFromClosestSource(room_name) => {
+                harvest::harvest_energy_from_closest_source(worker, *room_name, movement_profile)
+            }
+            Task::DeliverEnergyToClosestFillable(room_name) => {
+                logistics::deliver_energy_to_closest_fillable(worker, *room_name, movement_profile)
+            }
+            Task::AttackCreep(id) => defense::attack_creep(worker, id),
+            Task::HealCreep(id) => defense::heal_creep(worker, id),
+            Task::TransferEnergyToLink(id) => link::transfer_energy_to_link(worker, id),
+            Task::RenewAtSpawn(id) => lifecycle::renew_at_spawn(worker, *id, movement_profile),
+            Task::RecycleAtSpawn(id, position) => {
+                lifecycle::recycle_at_spawn(worker, *id, *position, movement_profile)
+            }
         }
     }
 }
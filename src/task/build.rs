@@ -24,12 +24,9 @@ pub fn build(
                             // likely got swapped out by a crowd
                             let avoid_creeps = creep.pos().get_range_to(construction_site.pos())
                                 == RANGED_OUT_OF_RANGE;
-                            let move_goal = MovementGoal {
-                                pos: construction_site.pos(),
-                                range: 1,
-                                profile: movement_profile,
-                                avoid_creeps,
-                            };
+                            let move_goal =
+                                MovementGoal::new(construction_site.pos(), 1, movement_profile)
+                                    .with_avoid_creeps(avoid_creeps);
                             TaskResult::MoveMeTo(move_goal)
                         }
                         ErrorCode::NotEnough => TaskResult::Complete,
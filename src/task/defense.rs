@@ -0,0 +1,44 @@
+use log::*;
+use screeps::{local::ObjectId, objects::Creep};
+
+use crate::{task::TaskResult, worker::WorkerReference};
+
+// keep shooting at a creep for as long as it's around
+pub fn attack_creep(worker: &WorkerReference, target: &ObjectId<Creep>) -> TaskResult {
+    match worker {
+        WorkerReference::Tower(tower) => match target.resolve() {
+            Some(creep) => match tower.attack(&creep) {
+                Ok(()) => TaskResult::StillWorking,
+                Err(e) => {
+                    info!("tower attack failure: {:?}", e);
+                    TaskResult::Complete
+                }
+            },
+            // dead or gone from the room
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
+
+// heal a creep back up to full
+pub fn heal_creep(worker: &WorkerReference, target: &ObjectId<Creep>) -> TaskResult {
+    match worker {
+        WorkerReference::Tower(tower) => match target.resolve() {
+            Some(creep) => {
+                if creep.hits() >= creep.hits_max() {
+                    return TaskResult::Complete;
+                }
+                match tower.heal(&creep) {
+                    Ok(()) => TaskResult::StillWorking,
+                    Err(e) => {
+                        info!("tower heal failure: {:?}", e);
+                        TaskResult::Complete
+                    }
+                }
+            }
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
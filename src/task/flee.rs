@@ -0,0 +1,105 @@
+use screeps::{
+    constants::{find, Part},
+    game,
+    local::Position,
+    objects::Creep,
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    movement::{DangerProfile, MovementGoal, MovementProfile},
+    task::TaskResult,
+    worker::WorkerReference,
+};
+
+// hostile creeps near the given creep that can hurt it, along with the range we'd want to keep
+// from each of them
+fn hostile_threats(creep: &Creep) -> Vec<(Position, u32)> {
+    let mut threats = vec![];
+    for hostile in creep
+        .pos()
+        .find_in_range(find::HOSTILE_CREEPS, RETREAT_SEARCH_RANGE)
+    {
+        if hostile.get_active_bodyparts(Part::RangedAttack) > 0 {
+            threats.push((hostile.pos(), RETREAT_RANGED_RANGE));
+        } else if hostile.get_active_bodyparts(Part::Attack) > 0 {
+            threats.push((hostile.pos(), RETREAT_MELEE_RANGE));
+        }
+    }
+    threats
+}
+
+// whether a creep has taken enough damage that it should get away from nearby hostiles
+pub fn should_retreat(creep: &Creep) -> bool {
+    creep.hits() * RETREAT_HITS_DIVISOR < creep.hits_max() && !hostile_threats(creep).is_empty()
+}
+
+fn flee_goal(mut threats: Vec<(Position, u32)>, movement_profile: MovementProfile) -> MovementGoal {
+    let (pos, range) = threats.remove(0);
+    MovementGoal::new(pos, range, movement_profile)
+        .fleeing(threats)
+        .with_danger(DangerProfile::Avoid)
+}
+
+pub fn retreat(
+    worker: &WorkerReference,
+    until_tick: u32,
+    fallback: Option<Position>,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => {
+            if game::time() >= until_tick {
+                return TaskResult::Complete;
+            }
+
+            let threats = hostile_threats(creep);
+            if threats.is_empty() {
+                return match fallback {
+                    // pull back to safety whether or not we can see what's out there
+                    Some(position) if creep.pos() != position => TaskResult::MoveMeTo(
+                        MovementGoal::new(position, 0, movement_profile)
+                            .with_danger(DangerProfile::Avoid),
+                    ),
+                    Some(_) => TaskResult::StillWorking,
+                    // nothing left around to run from
+                    None => TaskResult::Complete,
+                };
+            }
+
+            let move_goal = flee_goal(threats, movement_profile);
+            if move_goal.is_met(creep.pos()) {
+                // out of reach for now, hold here in case they follow
+                TaskResult::StillWorking
+            } else {
+                TaskResult::MoveMeTo(move_goal)
+            }
+        }
+        _ => panic!("unsupported worker type!"),
+    }
+}
+
+pub fn flee_from_position(
+    worker: &WorkerReference,
+    position: Position,
+    range: u32,
+    until_tick: u32,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => {
+            if game::time() >= until_tick {
+                return TaskResult::Complete;
+            }
+
+            let move_goal = flee_goal(vec![(position, range)], movement_profile);
+            if move_goal.is_met(creep.pos()) {
+                TaskResult::StillWorking
+            } else {
+                TaskResult::MoveMeTo(move_goal)
+            }
+        }
+        _ => panic!("unsupported worker type!"),
+    }
+}
@@ -1,7 +1,9 @@
 use log::*;
 use screeps::{
-    constants::{ErrorCode, ResourceType},
-    local::ObjectId,
+    constants::{find, look, ErrorCode, ResourceType, StructureType},
+    enums::StructureObject,
+    game,
+    local::{ObjectId, Position, RoomName},
     objects::Source,
     prelude::*,
 };
@@ -9,7 +11,7 @@ use screeps::{
 use crate::{
     constants::*,
     movement::{MovementGoal, MovementProfile},
-    task::TaskResult,
+    task::{Task, TaskResult},
     worker::WorkerReference,
 };
 
@@ -34,12 +36,8 @@ pub fn harvest_energy_until_full(
                         ErrorCode::NotInRange => {
                             let avoid_creeps =
                                 creep.pos().get_range_to(source.pos()) == MELEE_OUT_OF_RANGE;
-                            let move_goal = MovementGoal {
-                                pos: source.pos(),
-                                range: 1,
-                                profile: movement_profile,
-                                avoid_creeps,
-                            };
+                            let move_goal = MovementGoal::new(source.pos(), 1, movement_profile)
+                                .with_avoid_creeps(avoid_creeps);
                             TaskResult::MoveMeTo(move_goal)
                         }
                         ErrorCode::NotEnough => TaskResult::Complete,
@@ -69,12 +67,7 @@ pub fn harvest_energy_forever(
                     Ok(()) => TaskResult::StillWorking,
                     Err(e) => match e {
                         ErrorCode::NotInRange => {
-                            let move_goal = MovementGoal {
-                                pos: source.pos(),
-                                range: 1,
-                                profile: movement_profile,
-                                avoid_creeps: false,
-                            };
+                            let move_goal = MovementGoal::new(source.pos(), 1, movement_profile);
                             TaskResult::MoveMeTo(move_goal)
                         }
                         ErrorCode::NotEnough => TaskResult::StillWorking,
@@ -91,3 +84,156 @@ pub fn harvest_energy_forever(
         _ => panic!("unsupported worker type!"),
     }
 }
+
+// harvest from a fixed tile next to the source, building and keeping up the container there
+// and handing energy off to an adjacent link if there is one; whatever doesn't fit in the
+// creep's store drops into the container for haulers to collect
+pub fn harvest_energy_static(
+    worker: &WorkerReference,
+    target: &ObjectId<Source>,
+    mining_position: Position,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => {
+            if creep.pos() != mining_position {
+                let avoid_creeps = creep.pos().get_range_to(mining_position) <= MELEE_OUT_OF_RANGE;
+                let move_goal = MovementGoal::new(mining_position, 0, movement_profile)
+                    .with_avoid_creeps(avoid_creeps);
+                return TaskResult::MoveMeTo(move_goal);
+            }
+
+            let source = match target.resolve() {
+                Some(source) => source,
+                None => return TaskResult::Complete,
+            };
+
+            // spend what we're carrying on our container before anything else, when it's
+            // missing or worn down
+            if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+                let structures = mining_position
+                    .look_for(look::STRUCTURES)
+                    .unwrap_or_default();
+                let container = structures.iter().find_map(|structure| match structure {
+                    StructureObject::StructureContainer(container) => Some(container),
+                    _ => None,
+                });
+                match container {
+                    Some(container) => {
+                        if container.hits() * 100
+                            < container.hits_max() * HARVESTER_CONTAINER_REPAIR_PERCENT
+                            && creep.repair(container).is_ok()
+                        {
+                            return TaskResult::StillWorking;
+                        }
+                    }
+                    None => {
+                        let sites = mining_position
+                            .look_for(look::CONSTRUCTION_SITES)
+                            .unwrap_or_default();
+                        match sites.first() {
+                            Some(site) => {
+                                if creep.build(site).is_ok() {
+                                    return TaskResult::StillWorking;
+                                }
+                            }
+                            None => {
+                                if structures.is_empty() {
+                                    if let Err(e) = mining_position
+                                        .create_construction_site(StructureType::Container, None)
+                                    {
+                                        debug!("couldn't place mining container site: {:?}", e);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // once full, pass energy on to a link next to us if there's one with room
+            if creep.store().get_free_capacity(Some(ResourceType::Energy)) == 0 {
+                let link = mining_position
+                    .find_in_range(find::MY_STRUCTURES, 1)
+                    .into_iter()
+                    .find_map(|structure| match structure {
+                        StructureObject::StructureLink(link)
+                            if link.store().get_free_capacity(Some(ResourceType::Energy)) > 0 =>
+                        {
+                            Some(link)
+                        }
+                        _ => None,
+                    });
+                if let Some(link) = link {
+                    if let Err(e) = creep.transfer(&link, ResourceType::Energy, None) {
+                        info!("link transfer failure: {:?}", e);
+                    }
+                }
+            }
+
+            // every so often, finish up so the role gets a look at whether it's time to be
+            // renewed or replaced, otherwise it'd sit here until it dies
+            let check_lifecycle = game::time().is_multiple_of(HARVESTER_LIFECYCLE_CHECK_INTERVAL);
+            match creep.harvest(&source) {
+                // wait right here for it to regenerate if it's empty
+                Ok(()) | Err(ErrorCode::NotEnough) => {
+                    if check_lifecycle {
+                        TaskResult::Complete
+                    } else {
+                        TaskResult::StillWorking
+                    }
+                }
+                Err(e) => {
+                    info!("static harvest failure: {:?}", e);
+                    TaskResult::Complete
+                }
+            }
+        }
+        _ => panic!("unsupported worker type!"),
+    }
+}
+
+pub fn harvest_energy_from_closest_source(
+    worker: &WorkerReference,
+    room_name: RoomName,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => match game::rooms().get(room_name) {
+            Some(room) => {
+                let sources = room.find(find::SOURCES_ACTIVE, None);
+                let candidates = sources.iter().map(|source| (source.pos(), 1)).collect();
+                match MovementGoal::any_of(candidates, movement_profile, false) {
+                    Some(move_goal) => match move_goal.reached_goal(creep.pos()) {
+                        // we've arrived at one of the sources - switch to harvesting that one
+                        Some(reached_pos) => {
+                            match sources.iter().find(|source| source.pos() == reached_pos) {
+                                Some(source) => {
+                                    let source_id = source.id();
+                                    match harvest_energy_until_full(
+                                        worker,
+                                        &source_id,
+                                        movement_profile,
+                                    ) {
+                                        TaskResult::StillWorking => {
+                                            TaskResult::CompleteAddTaskToFront(
+                                                Task::HarvestEnergyUntilFull(source_id),
+                                            )
+                                        }
+                                        result => result,
+                                    }
+                                }
+                                None => TaskResult::Complete,
+                            }
+                        }
+                        None => TaskResult::MoveMeTo(move_goal),
+                    },
+                    // no sources with energy left in the room
+                    None => TaskResult::Complete,
+                }
+            }
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
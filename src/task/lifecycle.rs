@@ -0,0 +1,84 @@
+use log::*;
+use screeps::{
+    constants::ErrorCode,
+    local::{ObjectId, Position},
+    objects::StructureSpawn,
+    prelude::*,
+};
+
+use crate::{
+    constants::*,
+    movement::{MovementGoal, MovementProfile},
+    task::TaskResult,
+    worker::WorkerReference,
+};
+
+pub fn renew_at_spawn(
+    worker: &WorkerReference,
+    target: ObjectId<StructureSpawn>,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => {
+            if creep.ticks_to_live().unwrap_or(0) >= RENEW_TARGET_TTL {
+                return TaskResult::Complete;
+            }
+            match target.resolve() {
+                Some(spawn) => match spawn.renew_creep(creep) {
+                    Ok(()) => TaskResult::StillWorking,
+                    Err(e) => match e {
+                        ErrorCode::NotInRange => TaskResult::MoveMeTo(MovementGoal::new(
+                            spawn.pos(),
+                            1,
+                            movement_profile,
+                        )),
+                        // spawning something else, wait our turn
+                        ErrorCode::Busy => TaskResult::StillWorking,
+                        // as young as it gets, or the room's run out of energy to spend on it
+                        ErrorCode::Full => TaskResult::Complete,
+                        ErrorCode::NotEnough => TaskResult::Complete,
+                        e => {
+                            warn!("renew unhandled failure: {:?}", e);
+                            TaskResult::Complete
+                        }
+                    },
+                },
+                None => TaskResult::Complete,
+            }
+        }
+        _ => panic!("unsupported worker type!"),
+    }
+}
+
+// recycle from the given tile, which is a container next to the spawn so the energy given back
+// lands in it, or the spawn's own position to just get alongside it
+pub fn recycle_at_spawn(
+    worker: &WorkerReference,
+    target: ObjectId<StructureSpawn>,
+    position: Position,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => match target.resolve() {
+            Some(spawn) => {
+                let range = if position == spawn.pos() { 1 } else { 0 };
+                if creep.pos().get_range_to(position) > range {
+                    return TaskResult::MoveMeTo(MovementGoal::new(
+                        position,
+                        range,
+                        movement_profile,
+                    ));
+                }
+                match spawn.recycle_creep(creep) {
+                    Ok(()) => TaskResult::DestroyWorker,
+                    Err(e) => {
+                        warn!("recycle unhandled failure: {:?}", e);
+                        TaskResult::Complete
+                    }
+                }
+            }
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
@@ -0,0 +1,30 @@
+use log::*;
+use screeps::{constants::ErrorCode, local::ObjectId, objects::StructureLink};
+
+use crate::{task::TaskResult, worker::WorkerReference};
+
+// send everything in the link to another, waiting out the cooldown first
+pub fn transfer_energy_to_link(
+    worker: &WorkerReference,
+    target: &ObjectId<StructureLink>,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Link(link) => match target.resolve() {
+            Some(target_link) => {
+                if link.cooldown() > 0 {
+                    return TaskResult::StillWorking;
+                }
+                match link.transfer_energy(&target_link, None) {
+                    Ok(()) => TaskResult::Complete,
+                    Err(ErrorCode::Tired) => TaskResult::StillWorking,
+                    Err(e) => {
+                        info!("link transfer failure: {:?}", e);
+                        TaskResult::Complete
+                    }
+                }
+            }
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
@@ -1,9 +1,10 @@
 use log::*;
 use screeps::{
-    constants::{ErrorCode, ResourceType},
+    constants::{find, ErrorCode, ResourceType},
     enums::StructureObject,
-    local::ObjectId,
-    objects::{Resource, Structure},
+    game,
+    local::{ObjectId, RoomName},
+    objects::{Creep, Resource, Structure},
     prelude::*,
 };
 
@@ -25,13 +26,12 @@ pub fn take_from_resource(
                     Ok(()) => TaskResult::Complete,
                     Err(e) => match e {
                         ErrorCode::NotInRange => {
-                            let move_goal = MovementGoal {
-                                pos: resource.pos(),
-                                range: 1,
-                                // store is empty, no fatigue from carry parts - override with 5:1
-                                profile: MovementProfile::SwampFiveToOne,
-                                avoid_creeps: false,
-                            };
+                            // store is empty, no fatigue from carry parts - override with 5:1
+                            let move_goal = MovementGoal::new(
+                                resource.pos(),
+                                1,
+                                MovementProfile::SwampFiveToOne,
+                            );
                             TaskResult::MoveMeTo(move_goal)
                         }
                         ErrorCode::InvalidTarget => TaskResult::Complete,
@@ -66,13 +66,12 @@ pub fn take_from_structure(
                         Ok(()) => TaskResult::Complete,
                         Err(e) => match e {
                             ErrorCode::NotInRange => {
-                                let move_goal = MovementGoal {
-                                    pos: structure_object.pos(),
-                                    range: 1,
-                                    // store is empty, no fatigue from carry parts - override with 5:1
-                                    profile: MovementProfile::SwampFiveToOne,
-                                    avoid_creeps: false,
-                                };
+                                // store is empty, no fatigue from carry parts - override with 5:1
+                                let move_goal = MovementGoal::new(
+                                    structure_object.pos(),
+                                    1,
+                                    MovementProfile::SwampFiveToOne,
+                                );
                                 TaskResult::MoveMeTo(move_goal)
                             }
                             ErrorCode::InvalidTarget => TaskResult::Complete,
@@ -112,12 +111,8 @@ pub fn deliver_to_structure(
                         Ok(()) => TaskResult::Complete,
                         Err(e) => match e {
                             ErrorCode::NotInRange => {
-                                let move_goal = MovementGoal {
-                                    pos: structure_object.pos(),
-                                    range: 1,
-                                    profile: movement_profile,
-                                    avoid_creeps: false,
-                                };
+                                let move_goal =
+                                    MovementGoal::new(structure_object.pos(), 1, movement_profile);
                                 TaskResult::MoveMeTo(move_goal)
                             }
                             ErrorCode::InvalidTarget => TaskResult::Complete,
@@ -141,3 +136,92 @@ pub fn deliver_to_structure(
         _ => panic!("unsupported worker type!"),
     }
 }
+
+pub fn deliver_to_creep(
+    worker: &WorkerReference,
+    target: &ObjectId<Creep>,
+    resource_type: ResourceType,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => match target.resolve() {
+            Some(target_creep) => match creep.transfer(&target_creep, resource_type, None) {
+                Ok(()) => TaskResult::Complete,
+                Err(e) => match e {
+                    ErrorCode::NotInRange => {
+                        let move_goal = MovementGoal::new(target_creep.pos(), 1, movement_profile);
+                        TaskResult::MoveMeTo(move_goal)
+                    }
+                    ErrorCode::InvalidTarget => TaskResult::Complete,
+                    ErrorCode::NotEnough => TaskResult::Complete,
+                    ErrorCode::Full => TaskResult::Complete,
+                    e => {
+                        // failed for some other reason?
+                        warn!("transfer to creep unhandled failure: {:?}", e);
+                        TaskResult::Complete
+                    }
+                },
+            },
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
+
+pub fn deliver_energy_to_closest_fillable(
+    worker: &WorkerReference,
+    room_name: RoomName,
+    movement_profile: MovementProfile,
+) -> TaskResult {
+    match worker {
+        WorkerReference::Creep(creep) => match game::rooms().get(room_name) {
+            Some(room) => {
+                // spawns, extensions and towers that still have room for energy
+                let fillables: Vec<StructureObject> = room
+                    .find(find::MY_STRUCTURES, None)
+                    .into_iter()
+                    .filter(|structure| match structure {
+                        StructureObject::StructureSpawn(o) => {
+                            o.store().get_free_capacity(Some(ResourceType::Energy)) > 0
+                        }
+                        StructureObject::StructureExtension(o) => {
+                            o.store().get_free_capacity(Some(ResourceType::Energy)) > 0
+                        }
+                        StructureObject::StructureTower(o) => {
+                            o.store().get_free_capacity(Some(ResourceType::Energy)) > 0
+                        }
+                        _ => false,
+                    })
+                    .collect();
+                let candidates = fillables
+                    .iter()
+                    .map(|structure| (structure.pos(), 1))
+                    .collect();
+                match MovementGoal::any_of(candidates, movement_profile, false) {
+                    Some(move_goal) => match move_goal.reached_goal(creep.pos()) {
+                        // we've arrived next to one of them - fill that one
+                        Some(reached_pos) => {
+                            match fillables
+                                .iter()
+                                .find(|structure| structure.pos() == reached_pos)
+                            {
+                                Some(structure) => deliver_to_structure(
+                                    worker,
+                                    structure.as_structure().id(),
+                                    ResourceType::Energy,
+                                    movement_profile,
+                                ),
+                                None => TaskResult::Complete,
+                            }
+                        }
+                        None => TaskResult::MoveMeTo(move_goal),
+                    },
+                    // everything's full
+                    None => TaskResult::Complete,
+                }
+            }
+            None => TaskResult::Complete,
+        },
+        _ => panic!("unsupported worker type!"),
+    }
+}
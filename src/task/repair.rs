@@ -1,11 +1,15 @@
 use log::*;
 use screeps::{
-    constants::ErrorCode, enums::StructureObject, local::ObjectId, objects::Structure, prelude::*,
+    constants::{find, ErrorCode, ResourceType},
+    enums::StructureObject,
+    local::ObjectId,
+    objects::Structure,
+    prelude::*,
 };
 
 use crate::{
     constants::*,
-    movement::{MovementGoal, MovementProfile},
+    movement::{is_ally, MovementGoal, MovementProfile},
     task::TaskResult,
     worker::WorkerReference,
 };
@@ -13,11 +17,15 @@ use crate::{
 pub fn repair(
     worker: &WorkerReference,
     target: &ObjectId<Structure>,
+    target_hits: u32,
     movement_profile: MovementProfile,
 ) -> TaskResult {
     match worker {
         WorkerReference::Creep(creep) => match target.resolve() {
             Some(target_structure) => {
+                if target_structure.hits() >= target_hits {
+                    return TaskResult::Complete;
+                }
                 let structure_object = StructureObject::from(target_structure);
                 match structure_object.as_repairable() {
                     Some(repairable) => match creep.repair(repairable) {
@@ -26,12 +34,9 @@ pub fn repair(
                             ErrorCode::NotInRange => {
                                 let avoid_creeps = creep.pos().get_range_to(structure_object.pos())
                                     == RANGED_OUT_OF_RANGE;
-                                let move_goal = MovementGoal {
-                                    pos: structure_object.pos(),
-                                    range: 1,
-                                    profile: movement_profile,
-                                    avoid_creeps,
-                                };
+                                let move_goal =
+                                    MovementGoal::new(structure_object.pos(), 1, movement_profile)
+                                        .with_avoid_creeps(avoid_creeps);
                                 TaskResult::MoveMeTo(move_goal)
                             }
                             e => {
@@ -49,6 +54,36 @@ pub fn repair(
             // enum, and check for visibility (moving there if not visible) before removing
             None => TaskResult::Complete,
         },
+        WorkerReference::Tower(tower) => match target.resolve() {
+            Some(target_structure) => {
+                // hold back enough energy to fight with, and drop everything to fight if
+                // anything hostile turns up
+                let hostiles_present = tower.room().is_some_and(|room| {
+                    room.find(find::HOSTILE_CREEPS, None)
+                        .iter()
+                        .any(|creep| !is_ally(&creep.owner().username()))
+                });
+                if target_structure.hits() >= target_hits
+                    || hostiles_present
+                    || tower.store().get_used_capacity(Some(ResourceType::Energy))
+                        < TOWER_REPAIR_ENERGY_RESERVE
+                {
+                    return TaskResult::Complete;
+                }
+                let structure_object = StructureObject::from(target_structure);
+                match structure_object.as_repairable() {
+                    Some(repairable) => match tower.repair(repairable) {
+                        Ok(()) => TaskResult::StillWorking,
+                        Err(e) => {
+                            info!("tower repair failure: {:?}", e);
+                            TaskResult::Complete
+                        }
+                    },
+                    None => TaskResult::Complete,
+                }
+            }
+            None => TaskResult::Complete,
+        },
         _ => panic!("unsupported worker type!"),
     }
 }
@@ -1,19 +1,44 @@
 use log::*;
-use screeps::constants::ErrorCode;
+use screeps::{
+    constants::{look, Direction, ErrorCode, StructureType, Terrain},
+    game,
+    local::LocalRoomTerrain,
+    objects::{Room, SpawnOptions, StructureSpawn},
+    prelude::*,
+};
 
 use crate::{
-    role::WorkerRole,
+    role::{creep_name, WorkerRole},
     task::TaskResult,
     worker::{Worker, WorkerReference},
 };
 
-pub fn spawn_creep(worker: &WorkerReference, role: &WorkerRole) -> TaskResult {
+// spawn the role's body sized to the room's energy capacity, or when recovering, to the energy
+// that's in the room right now
+pub fn spawn_creep(worker: &WorkerReference, role: &WorkerRole, recovering: bool) -> TaskResult {
     match worker {
         WorkerReference::Spawn(spawn) => {
-            // serialize the name here and pass it through
-            let name = serde_json::to_string(&role).expect("roles should all serialize");
-            let body = role.get_body_for_creep(spawn);
-            match spawn.spawn_creep(&body, &name) {
+            // serialize the name here and pass it through, taking the first generation that
+            // isn't still alive
+            let creeps = game::creeps();
+            let name = (0..)
+                .map(|generation| creep_name(role, generation))
+                .find(|name| creeps.get(name.clone()).is_none())
+                .expect("some generation to be free");
+            let room = spawn.room().expect("spawn to have room");
+            let energy = if recovering {
+                room.energy_available()
+            } else {
+                room.energy_capacity_available()
+            };
+            let body = role.get_body_for_creep(energy);
+            let directions = spawn_directions(spawn, &room);
+            let options = if directions.is_empty() {
+                SpawnOptions::new()
+            } else {
+                SpawnOptions::new().directions(&directions)
+            };
+            match spawn.spawn_creep_with_options(&body, &name, &options) {
                 Ok(()) => TaskResult::Complete,
                 Err(e) => match e {
                     // already have a creep with this name
@@ -31,6 +56,52 @@ pub fn spawn_creep(worker: &WorkerReference, role: &WorkerRole) -> TaskResult {
     }
 }
 
+// directions to send newly spawned creeps, onto open tiles off the roads first so they don't
+// stand in the way in and out of the spawn, then onto the roads if that's all there is
+fn spawn_directions(spawn: &StructureSpawn, room: &Room) -> Vec<Direction> {
+    use Direction::*;
+    let terrain = LocalRoomTerrain::from(room.get_terrain());
+    let mut directions = vec![];
+    for direction in [
+        Top,
+        TopRight,
+        Right,
+        BottomRight,
+        Bottom,
+        BottomLeft,
+        Left,
+        TopLeft,
+    ] {
+        let pos = match spawn.pos().checked_add_direction(direction) {
+            Ok(pos) => pos,
+            Err(_) => continue,
+        };
+        if terrain.get(pos.xy()) == Terrain::Wall {
+            continue;
+        }
+        let structure_types: Vec<StructureType> = pos
+            .look_for(look::STRUCTURES)
+            .unwrap_or_default()
+            .iter()
+            .map(|structure| structure.as_structure().structure_type())
+            .collect();
+        let walkable = structure_types.iter().all(|ty| {
+            matches!(
+                ty,
+                StructureType::Road | StructureType::Container | StructureType::Rampart
+            )
+        });
+        if walkable {
+            directions.push((structure_types.contains(&StructureType::Road), direction));
+        }
+    }
+    directions.sort_by_key(|(road, _)| *road);
+    directions
+        .into_iter()
+        .map(|(_, direction)| direction)
+        .collect()
+}
+
 pub fn wait_to_spawn(worker: &WorkerReference) -> TaskResult {
     match worker {
         WorkerReference::Creep(creep) => {
@@ -21,12 +21,8 @@ pub fn upgrade(
                     ErrorCode::NotInRange => {
                         let avoid_creeps =
                             creep.pos().get_range_to(controller.pos()) == RANGED_OUT_OF_RANGE;
-                        let move_goal = MovementGoal {
-                            pos: controller.pos(),
-                            range: 1,
-                            profile: movement_profile,
-                            avoid_creeps,
-                        };
+                        let move_goal = MovementGoal::new(controller.pos(), 1, movement_profile)
+                            .with_avoid_creeps(avoid_creeps);
                         TaskResult::MoveMeTo(move_goal)
                     }
                     ErrorCode::NotEnough => TaskResult::Complete,
@@ -1,4 +1,4 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use enum_dispatch::enum_dispatch;
 use log::*;
@@ -7,18 +7,31 @@ use screeps::{
     constants::{find, Part},
     enums::StructureObject,
     game,
-    local::{ObjectId, Position},
-    objects::{Creep, Store, StructureSpawn, StructureTower},
+    local::{ObjectId, Position, RoomName},
+    objects::{Creep, Store, StructureLink, StructureSpawn, StructureTower},
     prelude::*,
 };
 
 use crate::{
-    movement::{MovementGoal, MovementProfile, PathState},
+    census::{CensusEntry, RoleCensus},
+    colony::ColonyState,
+    constants::*,
+    inter_shard,
+    logistics::LogisticsBroker,
+    movement::{DangerProfile, MovementGoal, MovementProfile, PathState},
+    reservation::ReservationLedger,
     role::*,
-    task::{Task, TaskResult},
+    task::{should_retreat, Task, TaskResult},
     ShardState,
 };
 
+/// What workers can see of the rest of the shard's state when finding a task
+pub struct TaskContext<'a> {
+    pub census: &'a RoleCensus,
+    pub colony_state: &'a mut HashMap<RoomName, ColonyState>,
+    pub reservations: &'a ReservationLedger,
+}
+
 /// Represents all of the different types of 'worker' object id we may have
 /// for resolving the objects each tick for work
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
@@ -26,6 +39,7 @@ pub enum WorkerId {
     Creep(ObjectId<Creep>),
     Spawn(ObjectId<StructureSpawn>),
     Tower(ObjectId<StructureTower>),
+    Link(ObjectId<StructureLink>),
 }
 
 impl WorkerId {
@@ -35,6 +49,7 @@ impl WorkerId {
             WorkerId::Creep(id) => id.resolve().map(WorkerReference::Creep),
             WorkerId::Spawn(id) => id.resolve().map(WorkerReference::Spawn),
             WorkerId::Tower(id) => id.resolve().map(WorkerReference::Tower),
+            WorkerId::Link(id) => id.resolve().map(WorkerReference::Link),
         }
     }
 }
@@ -48,6 +63,7 @@ pub enum WorkerReference {
     Creep(Creep),
     Spawn(StructureSpawn),
     Tower(StructureTower),
+    Link(StructureLink),
 }
 
 impl WorkerReference {
@@ -57,6 +73,7 @@ impl WorkerReference {
             WorkerReference::Creep(o) => o.pos(),
             WorkerReference::Spawn(o) => o.pos(),
             WorkerReference::Tower(o) => o.pos(),
+            WorkerReference::Link(o) => o.pos(),
         }
     }
 
@@ -74,6 +91,7 @@ impl WorkerReference {
             WorkerReference::Creep(o) => o.store(),
             WorkerReference::Spawn(o) => o.store(),
             WorkerReference::Tower(o) => o.store(),
+            WorkerReference::Link(o) => o.store(),
         }
     }
 }
@@ -84,16 +102,49 @@ impl WorkerReference {
 pub trait Worker {
     /// to be called for the worker when it has no work to do,
     /// so that it can find another task (even if it's just to idle)
-    fn find_task(&self, store: &Store, worker_roles: &HashSet<WorkerRole>) -> Task;
+    fn find_task(&self, worker: &WorkerReference, context: &mut TaskContext) -> Task;
 
-    /// gets the desired body to spawn a creep for a worker role
-    fn get_body_for_creep(&self, spawn: &StructureSpawn) -> Vec<Part>;
+    /// post any resources the worker has to give or wants brought to it - default to neither
+    fn post_logistics(
+        &self,
+        _worker: &WorkerReference,
+        _broker: &mut LogisticsBroker,
+        _reservations: &ReservationLedger,
+    ) {
+    }
+
+    /// something for a creep to do instead of its usual work as it gets old or outgrown, like
+    /// renewing or recycling itself - default to carrying on until it dies
+    fn find_lifecycle_task(&self, _creep: &Creep, _context: &mut TaskContext) -> Option<Task> {
+        None
+    }
+
+    /// gets the desired body to spawn a creep for a worker role, with the given energy to spend
+    fn get_body_for_creep(&self, energy: u32) -> Vec<Part>;
 
     /// movement profile for pathfinding - default to preferring roads
     fn get_movement_profile(&self) -> MovementProfile {
         MovementProfile::RoadsOneToTwo
     }
 
+    /// how far out of the way to go to keep out of reach of hostiles, for tasks that don't
+    /// have a preference of their own - default to not caring
+    fn get_danger_profile(&self) -> DangerProfile {
+        DangerProfile::Ignore
+    }
+
+    /// how important it is for this worker to get a path when the pathfinding budget is tight -
+    /// default to middling
+    fn get_path_priority(&self) -> u32 {
+        50
+    }
+
+    /// where the worker does its job, for timing replacements to get there as it expires -
+    /// default to nowhere in particular
+    fn get_work_position(&self) -> Option<Position> {
+        None
+    }
+
     /// whether the worker can move - defaults to true, should return false for structures
     fn can_move(&self) -> bool {
         true
@@ -107,6 +158,12 @@ pub struct WorkerState {
     pub worker_reference: Option<WorkerReference>,
     pub movement_goal: Option<MovementGoal>,
     pub path_state: Option<PathState>,
+    // ticks this worker has needed a path but been left out of the pathfinding budget
+    pub path_wait_ticks: u32,
+    // parking tile this worker has claimed while idle
+    pub parking: Option<Position>,
+    // where the worker was at the last movement step, to tell when it's taken a step
+    pub last_position: Option<Position>,
 }
 
 impl WorkerState {
@@ -121,6 +178,9 @@ impl WorkerState {
             worker_reference: Some(worker_reference),
             movement_goal: None,
             path_state: None,
+            path_wait_ticks: 0,
+            parking: None,
+            last_position: None,
         }
     }
 }
@@ -130,10 +190,11 @@ pub fn scan_and_register_creeps(shard_state: &mut ShardState) {
         // this function is called at the start of tick before any tasks, so we can simply assume
         // every creep has an id; if spawning had run then id-free creeps would be a possibility.
         let id = WorkerId::Creep(creep.try_id().expect("expected creep to have id!"));
+        let census_entry = CensusEntry::for_creep(id, &creep);
 
         // update the reference if there's already a worker for this creep id,
         // or parse the name and add it if it's not there
-        shard_state
+        let role = shard_state
             .worker_state
             .entry(id)
             .and_modify(|worker_state| {
@@ -143,18 +204,22 @@ pub fn scan_and_register_creeps(shard_state: &mut ShardState) {
                 worker_state.worker_reference = Some(WorkerReference::Creep(creep.clone()))
             })
             .or_insert_with(|| {
-                let creep_name: String = creep.name().into();
-                match serde_json::from_str(&creep_name) {
+                let creep_name = creep.name();
+                match parse_creep_name(&creep_name) {
                     Ok(role) => {
                         let task_queue = if creep.spawning() {
                             let mut queue = VecDeque::new();
                             queue.push_front(Task::WaitToSpawn);
                             queue
+                        } else if game::time() != shard_state.global_init_time {
+                            // a creep we haven't seen that isn't spawning has likely come
+                            // through a portal from another shard; pick up its orders if it
+                            // left any
+                            inter_shard::take_handoff(&creep_name, &shard_state.portals)
+                                .unwrap_or_default()
                         } else {
                             VecDeque::new()
                         };
-                        // add to hashset where we track which roles are filled by active workers
-                        shard_state.worker_roles.insert(role);
                         // then create the state struct
                         WorkerState::new_with_role_and_reference(
                             role,
@@ -164,8 +229,6 @@ pub fn scan_and_register_creeps(shard_state: &mut ShardState) {
                     }
                     Err(e) => {
                         warn!("couldn't parse creep name {}: {:?}", creep_name, e);
-                        // special case, don't insert to the hashset where we track roles, since
-                        // this isn't a valid role
                         let role = WorkerRole::Invalid(Invalid {});
                         WorkerState {
                             role,
@@ -173,10 +236,20 @@ pub fn scan_and_register_creeps(shard_state: &mut ShardState) {
                             worker_reference: Some(WorkerReference::Creep(creep)),
                             movement_goal: None,
                             path_state: None,
+                            path_wait_ticks: 0,
+                            parking: None,
+                            last_position: None,
                         }
                     }
                 }
-            });
+            })
+            .role;
+
+        // keep the census up to date with the creep's age, leaving out creeps whose names
+        // didn't parse since they aren't filling any role
+        if !matches!(role, WorkerRole::Invalid(_)) {
+            shard_state.census.record(role, census_entry);
+        }
     }
 }
 
@@ -185,13 +258,26 @@ pub fn scan_and_register_structures(shard_state: &mut ShardState) {
         // narrowing the scan down to just rooms that are owned currently,
         // as all structure types that are 'workers' in this bot can only
         // function in owned rooms
-        let owned = room
-            .controller()
-            .map_or(false, |controller| controller.my());
+        let owned = room.controller().is_some_and(|controller| controller.my());
 
         if owned {
             let room_name = room.name();
 
+            // track the room as a colony, refreshing anything that depends on its layout
+            match shard_state.colony_state.get_mut(&room_name) {
+                Some(colony_state) => colony_state.update_layout(&room, &shard_state.heatmap),
+                None => {
+                    if shard_state.saved_colonies.is_loaded() {
+                        let colony_state = ColonyState::new(
+                            &room,
+                            &shard_state.heatmap,
+                            shard_state.saved_colonies.get(room_name),
+                        );
+                        shard_state.colony_state.insert(room_name, colony_state);
+                    }
+                }
+            }
+
             for structure in room.find(find::MY_STRUCTURES, None) {
                 match structure {
                     StructureObject::StructureSpawn(spawn) => {
@@ -214,6 +300,19 @@ pub fn scan_and_register_structures(shard_state: &mut ShardState) {
                         );
                         shard_state.worker_state.insert(id, worker_state);
                     }
+                    StructureObject::StructureLink(link) => {
+                        let id = WorkerId::Link(link.id());
+                        let role = WorkerRole::Link(Link {
+                            room: room_name,
+                            kind: link_kind(&room, link.pos()),
+                        });
+                        let worker_state = WorkerState::new_with_role_and_reference(
+                            role,
+                            WorkerReference::Link(link),
+                            VecDeque::new(),
+                        );
+                        shard_state.worker_state.insert(id, worker_state);
+                    }
                     // we don't make workers for any other structure types!
                     _ => {}
                 }
@@ -222,10 +321,43 @@ pub fn scan_and_register_structures(shard_state: &mut ShardState) {
     }
 }
 
+// find nukes landing soon in any visible room, so creeps can get clear of them
+fn find_incoming_nukes() -> Vec<(Position, u32)> {
+    let mut nukes = vec![];
+    for room in game::rooms().values() {
+        for nuke in room.find(find::NUKES, None) {
+            let time_to_land = nuke.time_to_land();
+            if time_to_land <= NUKE_FLEE_TICKS {
+                nukes.push((nuke.pos(), game::time() + time_to_land + 1));
+            }
+        }
+    }
+    nukes
+}
+
+// whether a task is done with, one way or another
+fn is_finished(result: &TaskResult) -> bool {
+    matches!(
+        result,
+        TaskResult::Complete
+            | TaskResult::CompleteAddTaskToFront(_)
+            | TaskResult::CompleteAddTaskToBack(_)
+            | TaskResult::DestroyWorker
+    )
+}
+
 pub fn run_workers(shard_state: &mut ShardState) {
     // track which worker ids can't resolve and should be removed from the hashmap after iteration
     let mut remove_worker_ids = vec![];
-    let mut remove_worker_roles = vec![];
+
+    let incoming_nukes = find_incoming_nukes();
+
+    // parking tiles already taken by idle creeps
+    let mut claimed_parking: HashSet<Position> = shard_state
+        .worker_state
+        .values()
+        .filter_map(|worker_state| worker_state.parking)
+        .collect();
 
     for (worker_id, worker_state) in shard_state.worker_state.iter_mut() {
         if worker_state.worker_reference.is_none() {
@@ -238,7 +370,6 @@ pub fn run_workers(shard_state: &mut ShardState) {
                 None => {
                     // couldn't resolve the worker, mark it for removal
                     remove_worker_ids.push(*worker_id);
-                    remove_worker_roles.push(worker_state.role);
                     continue;
                 }
             }
@@ -247,17 +378,48 @@ pub fn run_workers(shard_state: &mut ShardState) {
         // we've either resolved the worker or continue has jumped out of the loop, unwrap
         let worker_ref = worker_state.worker_reference.as_ref().unwrap();
         let movement_profile = worker_state.role.get_movement_profile();
+        let danger_profile = worker_state.role.get_danger_profile();
+
+        // get out of harm's way before carrying on with whatever the worker was doing
+        if let WorkerReference::Creep(creep) = worker_ref {
+            let already_fleeing = matches!(
+                worker_state.task_queue.front(),
+                Some(Task::Retreat(..)) | Some(Task::FleeFromPosition(..))
+            );
+            if !already_fleeing {
+                let position = creep.pos();
+                if let Some((nuke_pos, land_tick)) = incoming_nukes
+                    .iter()
+                    .find(|(nuke_pos, _)| position.get_range_to(*nuke_pos) < NUKE_FLEE_RANGE)
+                {
+                    worker_state.task_queue.push_front(Task::FleeFromPosition(
+                        *nuke_pos,
+                        NUKE_FLEE_RANGE,
+                        *land_tick,
+                    ));
+                } else if should_retreat(creep) {
+                    worker_state
+                        .task_queue
+                        .push_front(Task::Retreat(game::time() + RETREAT_TICKS, None));
+                }
+            }
+        }
 
         match worker_state.task_queue.pop_front() {
             Some(task) => {
                 // we've got a task, run it!
-                match task.run_task(worker_ref, movement_profile) {
+                let result = task.run_task(worker_ref, movement_profile);
+                if is_finished(&result) {
+                    shard_state.reservations.release_task(*worker_id, &task);
+                }
+                match result {
                     // nothing to do if complete, already popped
                     TaskResult::Complete => {}
                     TaskResult::StillWorking => {
                         worker_state.task_queue.push_front(task);
                     }
-                    TaskResult::MoveMeTo(move_goal) => {
+                    TaskResult::MoveMeTo(mut move_goal) => {
+                        move_goal.danger.get_or_insert(danger_profile);
                         worker_state.movement_goal = Some(move_goal);
                         worker_state.task_queue.push_front(task)
                     }
@@ -266,32 +428,57 @@ pub fn run_workers(shard_state: &mut ShardState) {
                         worker_state.task_queue.push_front(task);
                         worker_state.task_queue.push_front(result_task);
                     }
+                    // follow-up tasks hold their targets just like newly found ones
                     TaskResult::CompleteAddTaskToFront(result_task) => {
+                        shard_state
+                            .reservations
+                            .reserve(*worker_id, &result_task, worker_ref);
                         worker_state.task_queue.push_front(result_task);
                     }
                     TaskResult::CompleteAddTaskToBack(result_task) => {
+                        shard_state
+                            .reservations
+                            .reserve(*worker_id, &result_task, worker_ref);
                         worker_state.task_queue.push_back(result_task);
                     }
                     TaskResult::DestroyWorker => {
                         remove_worker_ids.push(*worker_id);
-                        remove_worker_roles.push(worker_state.role);
                     }
                 }
             }
             None => {
                 // no task in queue, let's find one (even if it's just to go idle)
                 // include the worker's store and the worker role hashset
-                let new_task = worker_state
-                    .role
-                    .find_task(&worker_ref.store(), &shard_state.worker_roles);
-                match new_task.run_task(worker_ref, movement_profile) {
+                let mut context = TaskContext {
+                    census: &shard_state.census,
+                    colony_state: &mut shard_state.colony_state,
+                    reservations: &shard_state.reservations,
+                };
+                let lifecycle_task = match worker_ref {
+                    WorkerReference::Creep(creep) => {
+                        worker_state.role.find_lifecycle_task(creep, &mut context)
+                    }
+                    _ => None,
+                };
+                let new_task = lifecycle_task
+                    .unwrap_or_else(|| worker_state.role.find_task(worker_ref, &mut context));
+                // hold whatever the task's going for, so nobody else sets off for it too
+                shard_state
+                    .reservations
+                    .reserve(*worker_id, &new_task, worker_ref);
+                let result = new_task.run_task(worker_ref, movement_profile);
+                if is_finished(&result) {
+                    shard_state.reservations.release_task(*worker_id, &new_task);
+                }
+                match result {
                     TaskResult::Complete => {
                         warn!("instantly completed new task, unexpected: {:?}", new_task)
                     }
                     TaskResult::StillWorking => {
                         worker_state.task_queue.push_front(new_task);
                     }
-                    TaskResult::MoveMeTo(move_goal) => {
+                    TaskResult::MoveMeTo(mut move_goal) => {
+                        move_goal.danger.get_or_insert(danger_profile);
                         worker_state.movement_goal = Some(move_goal);
                         worker_state.task_queue.push_front(new_task)
                     }
@@ -300,26 +487,58 @@ pub fn run_workers(shard_state: &mut ShardState) {
                         worker_state.task_queue.push_front(new_task);
                         worker_state.task_queue.push_front(result_task);
                     }
+                    // follow-up tasks hold their targets just like newly found ones
                     TaskResult::CompleteAddTaskToFront(result_task) => {
+                        shard_state
+                            .reservations
+                            .reserve(*worker_id, &result_task, worker_ref);
                         worker_state.task_queue.push_front(result_task);
                     }
                     TaskResult::CompleteAddTaskToBack(result_task) => {
+                        shard_state
+                            .reservations
+                            .reserve(*worker_id, &result_task, worker_ref);
                         worker_state.task_queue.push_back(result_task);
                     }
                     TaskResult::DestroyWorker => {
                         remove_worker_ids.push(*worker_id);
-                        remove_worker_roles.push(worker_state.role);
                     }
                 }
             }
         }
-    }
 
-    for id in remove_worker_ids {
-        shard_state.worker_state.remove(&id);
+        // idle creeps get out of the way, to a parking tile in the colony they're in
+        let idle = matches!(worker_state.task_queue.front(), Some(Task::IdleUntil(_)));
+        if idle && worker_state.role.can_move() {
+            let position = worker_ref.pos();
+            let parking = worker_state.parking.or_else(|| {
+                shard_state
+                    .colony_state
+                    .get(&position.room_name())
+                    .and_then(|colony_state| {
+                        colony_state.closest_free_parking(position, &claimed_parking)
+                    })
+            });
+            if let Some(parking_pos) = parking {
+                claimed_parking.insert(parking_pos);
+                worker_state.parking = Some(parking_pos);
+                if position != parking_pos {
+                    worker_state.movement_goal = Some(
+                        MovementGoal::new(parking_pos, 0, movement_profile)
+                            .with_danger(danger_profile),
+                    );
+                }
+            }
+        } else if let Some(parking_pos) = worker_state.parking.take() {
+            // back to work, free up the spot
+            claimed_parking.remove(&parking_pos);
+        }
     }
 
-    for role in remove_worker_roles {
-        shard_state.worker_roles.remove(&role);
+    for id in remove_worker_ids {
+        if let Some(worker_state) = shard_state.worker_state.remove(&id) {
+            shard_state.census.remove(&worker_state.role, id);
+        }
+        shard_state.reservations.release(id);
     }
 }